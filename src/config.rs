@@ -1,48 +1,260 @@
-use std::path::PathBuf;
+pub mod diagnostics;
+pub mod keys;
+pub mod layout;
+pub mod migrate;
+pub mod overrides;
+pub mod reload;
+pub mod themes;
+
+use std::path::{Path, PathBuf};
 
-use home::home_dir;
 use ratatui::{style::Color, widgets::BorderType};
 use serde::{
-    de::{DeserializeOwned, Visitor},
+    de::{DeserializeOwned, Unexpected, Visitor},
     Deserialize,
 };
-use tokio::io::AsyncReadExt;
+
+use self::diagnostics::{ConfigErrors, Diagnostic, KeySegment, Severity};
+use self::overrides::Overrides;
+
+use self::{keys::KeysConfiguration, layout::LayoutConfiguration};
+
+/// Config written on the first launch, also the reference for default values
+pub const EXAMPLE_CONFIG: &str = include_str!("./config.example.toml");
 
 #[derive(Debug, Clone)]
 pub struct VersesConfig {
     pub api: ApiConfiguration,
     pub general: GeneralConfiguration,
     pub theme: ThemeConfiguration,
+    pub notifications: NotificationConfiguration,
+    pub hooks: HooksConfiguration,
+    pub plugins: PluginConfiguration,
+    pub keys: KeysConfiguration,
+    pub layout: LayoutConfiguration,
+    pub playback: PlaybackConfiguration,
+    pub mouse: MouseConfiguration,
+    pub album_art: AlbumArtConfiguration,
+    /// Files included by the config sections, watched for changes alongside the config
+    pub includes: Vec<PathBuf>,
+    /// Problems that do not prevent using the config, like unknown keys
+    pub warnings: Vec<Diagnostic>,
+    /// Profile merged into the config
+    pub profile: Option<String>,
+    /// Names of all profiles in the config
+    pub profiles: Vec<String>,
 }
 
 impl VersesConfig {
-    pub async fn read_from_str(str: &str) -> anyhow::Result<Self> {
-        let unresolved = toml::from_str::<VersesConfigUnresolved>(str)?;
-        let theme = unresolved.theme.resolve().await?;
-        let api = unresolved.api.resolve().await?;
-        let general = unresolved.general.resolve().await?;
+    pub async fn read_from_file(
+        path: &Path,
+        overrides: &Overrides,
+        profile: Option<&str>,
+    ) -> anyhow::Result<Self> {
+        let source = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|err| ConfigErrors(vec![Diagnostic::io(path, &err)]))?;
+        Self::read_from_str(&source, path, overrides, profile).await
+    }
+
+    /// Parses the config read from `path`, with the profile and overrides layered on top of it.
+    /// All problems are collected into [`ConfigErrors`], pointing at the files they were found in
+    pub async fn read_from_str(
+        source: &str,
+        path: &Path,
+        overrides: &Overrides,
+        profile: Option<&str>,
+    ) -> anyhow::Result<Self> {
+        let mut loader = ConfigLoader::new(SourceFile {
+            path: path.to_path_buf(),
+            source: source.to_owned(),
+        });
+        let mut root = match loader.load().await {
+            Some(root) => root,
+            None => return Err(ConfigErrors(loader.errors).into()),
+        };
+        let profiles = loader.apply_profile(&mut root, profile);
+        loader.apply_overrides(&mut root, overrides);
+        loader.check_unknown_keys(&root, &profiles);
+        loader.apply_preset(&mut root);
+
+        let theme = loader.section(&mut root, "theme");
+        let api = loader.section(&mut root, "api");
+        let general: GeneralConfiguration = loader.section(&mut root, "general");
+        let notifications: NotificationConfiguration = loader.section(&mut root, "notifications");
+        let hooks = loader.section(&mut root, "hooks");
+        let plugins = loader.section(&mut root, "plugins");
+        let keys: KeysConfiguration = loader.section(&mut root, "keys");
+        if let Err(err) = keys.validate() {
+            loader.key_error(&diagnostics::key(&["keys"]), format!("{err:#}"));
+        }
+        loader.remove_old_panes(&mut root);
+        let layout: LayoutConfiguration = loader.section(&mut root, "layout");
+        if let Err(err) = layout.validate() {
+            loader.key_error(&diagnostics::key(&["layout"]), format!("{err:#}"));
+        }
+        let playback = loader.section(&mut root, "playback");
+        let mouse = loader.section(&mut root, "mouse");
+        let album_art: AlbumArtConfiguration = loader.section(&mut root, "album_art");
+        let display = &general.display;
+        loader.check_templates(&[
+            (&["general", "display", "name_format"], &display.name_format),
+            (
+                &["general", "display", "artists_format"],
+                &display.artists_format,
+            ),
+            (
+                &["general", "display", "album_format"],
+                &display.album_format,
+            ),
+            (
+                &["general", "display", "genres_format"],
+                &display.genres_format,
+            ),
+            (
+                &["general", "display", "popularity_format"],
+                &display.popularity_format,
+            ),
+            (
+                &["notifications", "summary_format"],
+                &notifications.summary_format,
+            ),
+            (
+                &["notifications", "body_format"],
+                &notifications.body_format,
+            ),
+            (&["album_art", "url_format"], &album_art.url_format),
+        ]);
+
+        if !loader.errors.is_empty() {
+            loader.errors.append(&mut loader.warnings);
+            return Err(ConfigErrors(loader.errors).into());
+        }
         Ok(Self {
             theme,
             api,
             general,
+            notifications,
+            hooks,
+            plugins,
+            keys,
+            layout,
+            playback,
+            mouse,
+            album_art,
+            includes: loader.includes,
+            warnings: loader.warnings,
+            profile: profile.map(str::to_owned),
+            profiles: profiles.keys().cloned().collect(),
         })
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
 pub struct ThemeConfiguration {
     pub lyrics: ThemeLyrics,
     pub borders: ThemeBorders,
     pub progress_bar: ThemeProgress,
+    pub karaoke: ThemeKaraoke,
+    pub dynamic: ThemeDynamic,
 }
 
 #[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
 pub struct ThemeLyrics {
     pub inactive_text_color: ThemeColor,
     pub active_text_color: ThemeColor,
+    /// Color of lines before the active one, `inactive_text_color` is used if not set
+    pub past_text_color: Option<ThemeColor>,
+    /// Color of lines after the active one, `inactive_text_color` is used if not set
+    pub upcoming_text_color: Option<ThemeColor>,
+    /// Lines fade into `fade_color` over this many lines from the active one, 0 disables fading
+    pub fade_distance: u16,
+    pub fade_color: ThemeColor,
+    /// Duration of the color transition when the active line changes, 0 disables transitions
+    pub transition_ms: u64,
+    pub active_modifiers: Vec<TextModifier>,
+    pub inactive_modifiers: Vec<TextModifier>,
 }
 
+impl Default for ThemeLyrics {
+    fn default() -> Self {
+        Self {
+            inactive_text_color: ThemeColor(Color::Gray),
+            active_text_color: ThemeColor(Color::LightGreen),
+            past_text_color: None,
+            upcoming_text_color: None,
+            fade_distance: 0,
+            fade_color: ThemeColor(Color::DarkGray),
+            transition_ms: 300,
+            active_modifiers: Vec::new(),
+            inactive_modifiers: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextModifier {
+    Bold,
+    Dim,
+    Italic,
+    Underlined,
+}
+
+/// Karaoke view, showing the current line in large characters
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ThemeKaraoke {
+    /// Color of the current line, `lyrics.active_text_color` is used if not set
+    pub text_color: Option<ThemeColor>,
+    /// Color of the next line, `lyrics.inactive_text_color` is used if not set
+    pub next_text_color: Option<ThemeColor>,
+    pub scale: KaraokeScale,
+}
+
+/// Colors derived from the album art of the current track
 #[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ThemeDynamic {
+    pub enabled: bool,
+    /// Whether to override the lyrics colors
+    pub lyrics: bool,
+    /// Whether to override the progress bar color
+    pub progress: bool,
+    /// Background of the terminal, derived colors are kept readable against it
+    pub background: ThemeColor,
+    /// Minimal contrast ratio between the derived colors and the background, from 1 to 21
+    pub min_contrast: f32,
+}
+
+impl Default for ThemeDynamic {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            lyrics: true,
+            progress: true,
+            background: ThemeColor(Color::Black),
+            min_contrast: 4.5,
+        }
+    }
+}
+
+/// Size of the characters in the karaoke view
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KaraokeScale {
+    /// The largest size that fits into the terminal
+    #[default]
+    Auto,
+    Large,
+    Medium,
+    Small,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
 pub struct ThemeBorders {
     pub lyrics_border_color: ThemeColor,
     pub lyrics_border_text_color: ThemeColor,
@@ -53,28 +265,115 @@ pub struct ThemeBorders {
     pub info_border_style: BorderStyle,
 }
 
+impl Default for ThemeBorders {
+    fn default() -> Self {
+        Self {
+            lyrics_border_color: ThemeColor(Color::DarkGray),
+            lyrics_border_text_color: ThemeColor(Color::DarkGray),
+            lyrics_border_style: BorderStyle(BorderType::Rounded),
+            info_border_color: ThemeColor(Color::DarkGray),
+            info_border_text_color: ThemeColor(Color::DarkGray),
+            info_text_color: ThemeColor(Color::Gray),
+            info_border_style: BorderStyle(BorderType::Plain),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
 pub struct ThemeProgress {
     pub color: ThemeColor,
     pub is_percentage: bool,
 }
 
+impl Default for ThemeProgress {
+    fn default() -> Self {
+        Self {
+            color: ThemeColor(Color::LightBlue),
+            is_percentage: false,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
 pub struct ApiConfiguration {
+    /// Required to log in, there is no sensible default
     pub spotify_client_id: String,
     pub lyricstify_api_url: String,
+    /// Port of the `http://localhost:<port>/callback` redirect URI of the Spotify app
+    pub redirect_port: u16,
+}
+
+impl Default for ApiConfiguration {
+    fn default() -> Self {
+        Self {
+            spotify_client_id: String::new(),
+            lyricstify_api_url: "https://api.lyricstify.vercel.app/v1/lyrics/".to_owned(),
+            redirect_port: 8888,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
 pub struct GeneralConfiguration {
     pub romanize_unicode: bool,
     pub romanize_exclude: Vec<String>,
     pub romanize_track_names: bool,
     pub scroll_offset: u32,
+    pub scroll_mode: ScrollMode,
     pub display: DisplayConfig,
+    pub instrumental: InstrumentalConfiguration,
 }
 
+impl Default for GeneralConfiguration {
+    fn default() -> Self {
+        Self {
+            romanize_unicode: true,
+            romanize_exclude: vec!["de".to_owned()],
+            romanize_track_names: false,
+            scroll_offset: 4,
+            scroll_mode: ScrollMode::default(),
+            display: DisplayConfig::default(),
+            instrumental: InstrumentalConfiguration::default(),
+        }
+    }
+}
+
+/// Indicator shown during instrumental breaks, counting down to the next line
 #[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct InstrumentalConfiguration {
+    pub enabled: bool,
+    /// Gaps between two lines at least this long are considered instrumental breaks
+    pub min_gap_ms: u32,
+}
+
+impl Default for InstrumentalConfiguration {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_gap_ms: 10_000,
+        }
+    }
+}
+
+/// Where the active line is kept while autoscrolling
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScrollMode {
+    /// `scroll_offset` rows below the top
+    #[default]
+    TopOffset,
+    /// In the middle of the lyrics box
+    Center,
+    /// Lyrics are flipped a whole page at a time
+    Page,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
 pub struct DisplayConfig {
     pub show_name: bool,
     pub name_format: String,
@@ -88,6 +387,155 @@ pub struct DisplayConfig {
     pub popularity_format: String,
 }
 
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            show_name: false,
+            name_format: "• Name: {{ name }}".to_owned(),
+            show_artists: true,
+            artists_format: "• Artists: {{ join artists }}".to_owned(),
+            show_album: true,
+            album_format: "• Album: {{ album }}".to_owned(),
+            show_genres: true,
+            genres_format: "• Genres: {{ join genres }}".to_owned(),
+            show_popularity: true,
+            popularity_format: "• Popularity: {{ popularity }}%".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NotificationConfiguration {
+    pub enabled: bool,
+    pub summary_format: String,
+    pub body_format: String,
+    pub timeout_ms: i32,
+    /// Address of the D-Bus to send notifications to, session bus is used if not set
+    pub bus_address: Option<String>,
+}
+
+impl Default for NotificationConfiguration {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            summary_format: "{{ name }}".to_owned(),
+            body_format: "{{ join artists }} • {{ album }}{{#unless has_lyrics}}\n(no synchronized lyrics){{/unless}}".to_owned(),
+            timeout_ms: 5000,
+            bus_address: None,
+        }
+    }
+}
+
+/// Shell commands executed on playback events
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HooksConfiguration {
+    pub on_track_change: Option<String>,
+    pub on_line_change: Option<String>,
+    pub on_no_lyrics: Option<String>,
+    pub on_pause: Option<String>,
+    /// Minimal interval between two executions of the same hook
+    pub min_interval_ms: u64,
+}
+
+impl HooksConfiguration {
+    pub fn any_enabled(&self) -> bool {
+        self.on_track_change.is_some()
+            || self.on_line_change.is_some()
+            || self.on_no_lyrics.is_some()
+            || self.on_pause.is_some()
+    }
+}
+
+impl Default for HooksConfiguration {
+    fn default() -> Self {
+        Self {
+            on_track_change: None,
+            on_line_change: None,
+            on_no_lyrics: None,
+            on_pause: None,
+            min_interval_ms: 500,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PluginConfiguration {
+    pub enabled: bool,
+    /// Maximal amount of operations a single plugin function call may perform
+    pub max_operations: u64,
+}
+
+impl Default for PluginConfiguration {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_operations: 100_000,
+        }
+    }
+}
+
+/// Controlling Spotify playback from verses, requires Spotify Premium
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PlaybackConfiguration {
+    /// Allows seeking by clicking lyric lines and the progress bar.
+    /// Enabling this requires logging in again, to grant verses the permission
+    pub control: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MouseConfiguration {
+    pub enabled: bool,
+    /// Amount of rows scrolled with a single wheel step
+    pub scroll_lines: u16,
+}
+
+impl Default for MouseConfiguration {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            scroll_lines: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AlbumArtConfiguration {
+    pub enabled: bool,
+    /// URL the cover is downloaded from.
+    /// Available templates: `url` (the Spotify image URL) and `album_id`
+    pub url_format: String,
+    pub protocol: GraphicsProtocol,
+}
+
+impl Default for AlbumArtConfiguration {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            url_format: "{{ url }}".to_owned(),
+            protocol: GraphicsProtocol::Auto,
+        }
+    }
+}
+
+/// How images are drawn in the terminal
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GraphicsProtocol {
+    /// Detected from the terminal environment
+    #[default]
+    Auto,
+    /// `▀` characters, supported by every truecolor terminal
+    HalfBlocks,
+    Kitty,
+    Sixel,
+}
+
 #[derive(Debug, Clone)]
 #[repr(transparent)]
 pub struct ThemeColor(pub Color);
@@ -107,7 +555,7 @@ impl<'v> Visitor<'v> for ColorVisitor {
     type Value = ThemeColor;
 
     fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(formatter, "a string representing a color")
+        write!(formatter, "a color name or a `#rrggbb` hex color")
     }
 
     fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
@@ -115,16 +563,15 @@ impl<'v> Visitor<'v> for ColorVisitor {
         E: serde::de::Error,
     {
         if let Some(stripped) = v.strip_prefix('#') {
-            let color_rgb = u32::from_str_radix(stripped, 16).map_err(|e| {
-                serde::de::Error::custom(format!("Invalid hex string for color {e}"))
-            })?;
+            let color_rgb = u32::from_str_radix(stripped, 16)
+                .map_err(|_| E::invalid_value(Unexpected::Str(v), &self))?;
             let r = (color_rgb & 0xFF0000) >> 16;
             let g = (color_rgb & 0x00FF00) >> 8;
-            let b = color_rgb * 0x0000FF;
+            let b = color_rgb & 0x0000FF;
             Ok(ThemeColor(Color::Rgb(r as u8, g as u8, b as u8)))
         } else {
             v.parse::<Color>()
-                .map_err(|e| serde::de::Error::custom(format!("Invalid named color format {e}")))
+                .map_err(|_| E::invalid_value(Unexpected::Str(v), &self))
                 .map(ThemeColor)
         }
     }
@@ -149,7 +596,10 @@ impl<'v> Visitor<'v> for BorderVisitor {
     type Value = BorderType;
 
     fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(formatter, "a string representing a border color")
+        write!(
+            formatter,
+            "a border style, one of `Plain`, `Rounded`, `Double`, `Thick`"
+        )
     }
 
     fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
@@ -157,35 +607,682 @@ impl<'v> Visitor<'v> for BorderVisitor {
         E: serde::de::Error,
     {
         v.parse::<BorderType>()
-            .map_err(|e| serde::de::Error::custom(format!("Invalid border style: {e}")))
+            .map_err(|_| E::invalid_value(Unexpected::Str(v), &self))
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
-struct VersesConfigUnresolved {
-    general: MaybeLink<GeneralConfiguration>,
-    api: MaybeLink<ApiConfiguration>,
-    theme: MaybeLink<ThemeConfiguration>,
+#[derive(Clone)]
+struct SourceFile {
+    path: PathBuf,
+    source: String,
 }
 
-#[derive(Debug, Clone, Deserialize)]
-#[serde(untagged)]
-pub enum MaybeLink<V> {
-    Explicit(V),
-    Link { include: PathBuf },
+/// A file or override the config was assembled from
+struct Origin {
+    /// Key the contents were placed at, included files are placed at the table including them
+    prefix: Vec<KeySegment>,
+    file: SourceFile,
+    /// Key the contents are at in the file, profiles are moved out of `profiles.<name>`
+    file_prefix: Vec<KeySegment>,
+    /// Contents before merging, to find out which keys were set here
+    table: toml::Value,
 }
 
-impl<V: DeserializeOwned> MaybeLink<V> {
-    async fn resolve(self) -> anyhow::Result<V> {
-        match self {
-            MaybeLink::Explicit(value) => Ok(value),
-            MaybeLink::Link { include } => {
-                let config_dir = home_dir().unwrap().join(".config").join("verses");
-                let mut file = tokio::fs::File::open(config_dir.join(include)).await?;
-                let mut str = String::new();
-                file.read_to_string(&mut str).await?;
-                toml::from_str(&str).map_err(anyhow::Error::from)
+/// Reads config sections, collecting problems instead of stopping at the first one
+struct ConfigLoader {
+    main: SourceFile,
+    errors: Vec<Diagnostic>,
+    warnings: Vec<Diagnostic>,
+    includes: Vec<PathBuf>,
+    /// In the reverse order of precedence
+    origins: Vec<Origin>,
+}
+
+impl ConfigLoader {
+    fn new(main: SourceFile) -> Self {
+        Self {
+            main,
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            includes: Vec::new(),
+            origins: Vec::new(),
+        }
+    }
+
+    fn parse(&mut self, file: &SourceFile) -> Option<toml::Table> {
+        match toml::from_str::<toml::Table>(&file.source) {
+            Ok(table) => Some(table),
+            Err(err) => {
+                self.errors
+                    .push(Diagnostic::syntax(&file.path, &file.source, &err));
+                None
+            }
+        }
+    }
+
+    /// Parses the main file and everything it includes
+    async fn load(&mut self) -> Option<toml::Table> {
+        let main = SourceFile {
+            path: self.main.path.clone(),
+            source: self.main.source.clone(),
+        };
+        let mut root = self.parse(&main)?;
+        let raw = toml::Value::Table(root.clone());
+        let mut stack = vec![canonicalize(&main.path).await];
+        self.resolve_includes(&mut root, &main, &[], 0, &mut stack)
+            .await;
+        self.origins.push(Origin {
+            prefix: Vec::new(),
+            file: main,
+            file_prefix: Vec::new(),
+            table: raw,
+        });
+        Some(root)
+    }
+
+    /// Replaces `include = "path"` keys of the table and its subtables with the contents of
+    /// the included files, recursively. Paths are relative to the including file, and the
+    /// other keys of the table override the included ones. `stack` holds the including files
+    async fn resolve_includes(
+        &mut self,
+        table: &mut toml::Table,
+        file: &SourceFile,
+        key: &[KeySegment],
+        file_start: usize,
+        stack: &mut Vec<PathBuf>,
+    ) {
+        let included = match table.remove("include") {
+            Some(toml::Value::String(include)) => {
+                Box::pin(self.include(&include, file, key, file_start, stack)).await
+            }
+            Some(_) => {
+                self.error(
+                    file,
+                    &include_key(key, file_start),
+                    "`include` must be a path to a file".to_owned(),
+                );
+                None
+            }
+            None => None,
+        };
+
+        for (name, value) in table.iter_mut() {
+            if let toml::Value::Table(subtable) = value {
+                let key = [key, &[KeySegment::Key(name.clone())]].concat();
+                Box::pin(self.resolve_includes(subtable, file, &key, file_start, stack)).await;
+            }
+        }
+
+        if let Some(mut included) = included {
+            themes::merge(&mut included, std::mem::take(table));
+            *table = included;
+        }
+    }
+
+    /// Reads and resolves a file included at the key
+    async fn include(
+        &mut self,
+        include: &str,
+        file: &SourceFile,
+        key: &[KeySegment],
+        file_start: usize,
+        stack: &mut Vec<PathBuf>,
+    ) -> Option<toml::Table> {
+        let path = match file.path.parent() {
+            Some(dir) => dir.join(include),
+            None => PathBuf::from(include),
+        };
+        self.includes.push(path.clone());
+        let canonical = canonicalize(&path).await;
+        if stack.contains(&canonical) {
+            let cycle = stack
+                .iter()
+                .chain([&canonical])
+                .map(|it| it.display().to_string())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            self.error(
+                file,
+                &include_key(key, file_start),
+                format!("include cycle: {cycle}"),
+            );
+            return None;
+        }
+        let source = match tokio::fs::read_to_string(&path).await {
+            Ok(source) => source,
+            Err(err) => {
+                self.error(
+                    file,
+                    &include_key(key, file_start),
+                    format!("failed to read `{}`: {err}", path.display()),
+                );
+                return None;
+            }
+        };
+        let included = SourceFile { path, source };
+        let mut table = self.parse(&included)?;
+        let raw = toml::Value::Table(table.clone());
+
+        stack.push(canonical);
+        Box::pin(self.resolve_includes(&mut table, &included, key, key.len(), stack)).await;
+        stack.pop();
+        self.origins.push(Origin {
+            prefix: key.to_vec(),
+            file: included,
+            file_prefix: Vec::new(),
+            table: raw,
+        });
+        Some(table)
+    }
+
+    /// Takes the profiles out of the config and merges the selected one into it
+    fn apply_profile(&mut self, root: &mut toml::Table, profile: Option<&str>) -> toml::Table {
+        let key = diagnostics::key(&["profiles"]);
+        let profiles = match root.remove("profiles") {
+            Some(toml::Value::Table(profiles)) => profiles,
+            Some(_) => {
+                self.key_error(&key, "profiles must be tables".to_owned());
+                return toml::Table::new();
+            }
+            None => toml::Table::new(),
+        };
+        let name = match profile {
+            Some(name) => name,
+            None => return profiles,
+        };
+        let profile_key = diagnostics::key(&["profiles", name]);
+        let table = match profiles.get(name) {
+            Some(toml::Value::Table(table)) => table.clone(),
+            Some(_) => {
+                self.key_error(&profile_key, "profile must be a table".to_owned());
+                return profiles;
+            }
+            None => {
+                let suggestion =
+                    diagnostics::did_you_mean(name, profiles.keys().map(String::as_str));
+                self.errors.push(
+                    self.at_key(Severity::Error, &key)
+                        .message(format!("unknown profile `{name}`"))
+                        .suggestion(suggestion),
+                );
+                return profiles;
+            }
+        };
+        let (file, file_prefix) = match self.origin_of(&profile_key) {
+            Some((file, file_key)) => (file.clone(), file_key),
+            None => (self.main.clone(), profile_key),
+        };
+        self.origins.push(Origin {
+            prefix: Vec::new(),
+            file,
+            file_prefix,
+            table: toml::Value::Table(table.clone()),
+        });
+        themes::merge(root, table);
+        profiles
+    }
+
+    fn apply_overrides(&mut self, root: &mut toml::Table, overrides: &Overrides) {
+        for it in &overrides.0 {
+            let key: Vec<_> = it
+                .key
+                .iter()
+                .map(|it| KeySegment::Key(it.clone()))
+                .collect();
+            let file = SourceFile {
+                path: PathBuf::from(&it.origin),
+                source: String::new(),
+            };
+            let mut table = toml::Table::new();
+            if let Err(err) = it.apply(root).and_then(|_| it.apply(&mut table)) {
+                self.error(&file, &key, format!("{err:#}"));
+            }
+            self.origins.push(Origin {
+                prefix: Vec::new(),
+                file,
+                file_prefix: Vec::new(),
+                table: toml::Value::Table(table),
+            });
+        }
+    }
+
+    /// File the key was set in, and the key in that file. Overrides take precedence
+    /// over the profile, which takes precedence over files and files over the files they include
+    fn origin_of(&self, key: &[KeySegment]) -> Option<(&SourceFile, Vec<KeySegment>)> {
+        self.origins
+            .iter()
+            .rev()
+            .filter(|it| key.starts_with(&it.prefix))
+            .find(|it| diagnostics::value_at(&it.table, &key[it.prefix.len()..]).is_some())
+            .map(|it| {
+                let file_key = [&it.file_prefix, &key[it.prefix.len()..]].concat();
+                (&it.file, file_key)
+            })
+    }
+
+    /// Diagnostic pointing at the place the key was set at
+    fn at_key(&self, severity: Severity, key: &[KeySegment]) -> Diagnostic {
+        match self.origin_of(key) {
+            Some((file, file_key)) => {
+                Diagnostic::at_key(severity, &file.path, &file.source, &file_key)
+            }
+            None => Diagnostic::at_key(severity, &self.main.path, &self.main.source, key),
+        }
+    }
+
+    fn error(&mut self, file: &SourceFile, key: &[KeySegment], message: String) {
+        self.errors.push(
+            Diagnostic::at_key(Severity::Error, &file.path, &file.source, key).message(message),
+        );
+    }
+
+    fn key_error(&mut self, key: &[KeySegment], message: String) {
+        self.errors
+            .push(self.at_key(Severity::Error, key).message(message));
+    }
+
+    /// Replaces `theme.preset` with the values of the preset
+    fn apply_preset(&mut self, root: &mut toml::Table) {
+        let theme = match root.get_mut("theme") {
+            Some(theme) => theme,
+            None => return,
+        };
+        let key = diagnostics::key(&["theme", "preset"]);
+        match theme.get("preset") {
+            Some(toml::Value::String(name)) => {
+                let names = themes::PRESETS.map(|(name, _, _)| name);
+                if !names.contains(&name.as_str()) {
+                    let suggestion = diagnostics::did_you_mean(name, names);
+                    self.errors.push(
+                        self.at_key(Severity::Error, &key)
+                            .message(format!("unknown theme preset `{name}`"))
+                            .suggestion(suggestion),
+                    );
+                    return;
+                }
+            }
+            Some(_) => {
+                self.key_error(&key, "theme preset must be a string".to_owned());
+                return;
+            }
+            None => return,
+        }
+        if let Err(err) = themes::apply_preset(theme) {
+            self.key_error(&key, format!("{err:#}"));
+        }
+    }
+
+    /// Reports keys that are not documented in the example config, suggesting similar ones
+    fn check_unknown_keys(&mut self, root: &toml::Table, profiles: &toml::Table) {
+        let documented = migrate::documented_keys();
+        let mut table = root.clone();
+        table.insert("profiles".to_owned(), toml::Value::Table(profiles.clone()));
+        for path in migrate::unknown_keys(&table, &documented) {
+            // keys of profiles are the same as the ones of the config
+            let relative = match path.strip_prefix("profiles.") {
+                Some(path) => path.split_once('.').map_or(path, |it| it.1),
+                None => &path,
+            };
+            let (parent, name) = relative.rsplit_once('.').unwrap_or(("", relative));
+            let siblings = documented.iter().filter_map(|it| {
+                let (it_parent, it_name) = it.rsplit_once('.').unwrap_or(("", it));
+                (it_parent == parent).then_some(it_name)
+            });
+            let suggestion = diagnostics::did_you_mean(name, siblings);
+            let key = diagnostics::key(&path.split('.').collect::<Vec<_>>());
+            let warning = self
+                .at_key(Severity::Warning, &key)
+                .message("unknown key, it is not used by verses")
+                .suggestion(suggestion);
+            // keys of the selected profile are found both in the profile and in the config
+            if !self
+                .warnings
+                .iter()
+                .any(|it| it.file == warning.file && it.key == warning.key)
+            {
+                self.warnings.push(warning);
+            }
+        }
+    }
+
+    /// Compiles the handlebars templates, so invalid ones are rejected before they are rendered
+    fn check_templates(&mut self, templates: &[(&[&str], &str)]) {
+        for (key, template) in templates {
+            if let Err(err) = handlebars::Template::compile(template) {
+                self.key_error(&diagnostics::key(key), format!("Invalid template: {err}"));
+            }
+        }
+    }
+
+    /// Skips panes removed from the layout, so layouts written for older versions still load
+    fn remove_old_panes(&mut self, root: &mut toml::Table) {
+        let layout = match root.get_mut("layout") {
+            Some(layout) => layout,
+            None => return,
+        };
+        for key in layout::remove_old_panes(layout) {
+            let key = [diagnostics::key(&["layout"]), key].concat();
+            let warning = self.at_key(Severity::Warning, &key).message(
+                "this pane was removed and is skipped, key hints are shown by `?` instead",
+            );
+            self.warnings.push(warning);
+        }
+    }
+
+    /// Deserializes a section, missing and invalid sections use defaults
+    fn section<T: DeserializeOwned + Default>(&mut self, root: &mut toml::Table, name: &str) -> T {
+        let mut value = match root.remove(name) {
+            Some(value) => value,
+            None => return T::default(),
+        };
+        let mut errors = Vec::new();
+        let section = loop {
+            let err = match serde_path_to_error::deserialize::<_, T>(value.clone()) {
+                Ok(section) => break section,
+                Err(err) => err,
+            };
+            let path = KeySegment::from_path(err.path());
+            let key = diagnostics::key(&[name])
+                .into_iter()
+                .chain(path.clone())
+                .collect::<Vec<_>>();
+            errors.push(
+                self.at_key(Severity::Error, &key)
+                    .deserialize_error(err.inner().message(), diagnostics::value_at(&value, &path)),
+            );
+            // serde stops at the first error, the key is dropped to find the errors after it
+            if !diagnostics::remove_at(&mut value, &path) {
+                break T::default();
+            }
+        };
+        if errors.is_empty() {
+            return section;
+        }
+        // keys are visited in alphabetical order, errors are reported in the order of the file
+        errors.sort_by_key(|it| (it.file.clone(), it.line, it.column));
+        self.errors.append(&mut errors);
+        T::default()
+    }
+}
+
+/// Key of the `include` in the table at `key`, relative to the file starting at `file_start`
+fn include_key(key: &[KeySegment], file_start: usize) -> Vec<KeySegment> {
+    [&key[file_start..], &[KeySegment::Key("include".to_owned())]].concat()
+}
+
+/// Paths of missing files are kept as is, they are reported when reading them
+async fn canonicalize(path: &Path) -> PathBuf {
+    tokio::fs::canonicalize(path)
+        .await
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn read(source: &str, overrides: &Overrides) -> anyhow::Result<VersesConfig> {
+        VersesConfig::read_from_str(source, Path::new("config.toml"), overrides, None).await
+    }
+
+    /// Directory with config files, removed after the test
+    struct ConfigDir(PathBuf);
+
+    impl ConfigDir {
+        fn new(name: &str, files: &[(&str, &str)]) -> Self {
+            let dir = std::env::temp_dir().join(format!("verses-{name}-{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            for (path, source) in files {
+                let path = dir.join(path);
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(path, source).unwrap();
             }
+            Self(dir)
+        }
+
+        async fn read(&self, profile: Option<&str>) -> anyhow::Result<VersesConfig> {
+            let path = self.0.join("config.toml");
+            VersesConfig::read_from_file(&path, &Overrides::default(), profile).await
+        }
+    }
+
+    impl Drop for ConfigDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn resolves_nested_includes_relative_to_the_including_file() {
+        let dir = ConfigDir::new(
+            "nested-include",
+            &[
+                (
+                    "config.toml",
+                    "[theme]\ninclude = \"themes/main.toml\"\n[theme.lyrics]\ntransition_ms = 50\n",
+                ),
+                (
+                    "themes/main.toml",
+                    "include = \"base.toml\"\n[lyrics]\nfade_distance = 3\n",
+                ),
+                (
+                    "themes/base.toml",
+                    "[lyrics]\nfade_distance = 1\ntransition_ms = 100\nactive_modifiers = [\"bold\"]\n",
+                ),
+            ],
+        );
+        let config = dir.read(None).await.unwrap();
+        let lyrics = &config.theme.lyrics;
+        // files take precedence over the files they include, and keys next to `include` over both
+        assert_eq!(lyrics.fade_distance, 3);
+        assert_eq!(lyrics.transition_ms, 50);
+        assert_eq!(lyrics.active_modifiers, vec![TextModifier::Bold]);
+        assert_eq!(config.includes.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn reports_self_include() {
+        let dir = ConfigDir::new(
+            "self-include",
+            &[(
+                "config.toml",
+                "include = \"config.toml\"\n[general]\nscroll_offset = 2\n",
+            )],
+        );
+        let err = dir.read(None).await.unwrap_err().to_string();
+        assert!(err.contains("include cycle"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn reports_include_cycles() {
+        let dir = ConfigDir::new(
+            "include-cycle",
+            &[
+                ("config.toml", "[theme]\ninclude = \"a.toml\"\n"),
+                ("a.toml", "include = \"b.toml\"\n"),
+                ("b.toml", "include = \"a.toml\"\n"),
+            ],
+        );
+        let err = dir.read(None).await.unwrap_err().to_string();
+        assert!(err.contains("include cycle"), "{err}");
+        assert!(err.contains("b.toml:1"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn includes_the_same_file_twice_without_a_cycle() {
+        let dir = ConfigDir::new(
+            "diamond-include",
+            &[
+                (
+                    "config.toml",
+                    "[profiles.a]\ninclude = \"a.toml\"\n[profiles.b]\ninclude = \"b.toml\"\n",
+                ),
+                (
+                    "a.toml",
+                    "include = \"common.toml\"\n[general]\nscroll_offset = 1\n",
+                ),
+                ("b.toml", "include = \"common.toml\"\n"),
+                (
+                    "common.toml",
+                    "[general]\nscroll_offset = 9\nromanize_unicode = false\n",
+                ),
+            ],
+        );
+        let a = dir.read(Some("a")).await.unwrap();
+        assert_eq!(a.general.scroll_offset, 1);
+        assert!(!a.general.romanize_unicode);
+        let b = dir.read(Some("b")).await.unwrap();
+        assert_eq!(b.general.scroll_offset, 9);
+        assert!(!b.general.romanize_unicode);
+    }
+
+    fn color(value: &str) -> Color {
+        ThemeColor::deserialize(toml::Value::String(value.to_owned()))
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn parses_hex_colors() {
+        assert_eq!(color("#0000ff"), Color::Rgb(0, 0, 255));
+        assert_eq!(color("#ff0000"), Color::Rgb(255, 0, 0));
+        assert_eq!(color("#12ab3c"), Color::Rgb(0x12, 0xab, 0x3c));
+        assert_eq!(color("lightgreen"), Color::LightGreen);
+    }
+
+    #[tokio::test]
+    async fn overrides_take_precedence_over_the_file() {
+        let source = "[general]\nscroll_offset = 2\nromanize_unicode = false\n";
+        let overrides = Overrides(vec![overrides::Override {
+            origin: "--set".to_owned(),
+            key: vec!["general".to_owned(), "scroll_offset".to_owned()],
+            value: toml::Value::Integer(7),
+        }]);
+        let config = read(source, &overrides).await.unwrap();
+        assert_eq!(config.general.scroll_offset, 7);
+        assert!(!config.general.romanize_unicode);
+    }
+
+    #[tokio::test]
+    async fn invalid_overrides_point_at_their_origin() {
+        let overrides = Overrides(vec![overrides::Override {
+            origin: "$VERSES_GENERAL__SCROLL_OFFSET".to_owned(),
+            key: vec!["general".to_owned(), "scroll_offset".to_owned()],
+            value: toml::Value::String("many".to_owned()),
+        }]);
+        let err = read("", &overrides).await.unwrap_err().to_string();
+        assert!(err.contains("$VERSES_GENERAL__SCROLL_OFFSET"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn example_config_matches_the_defaults() {
+        let client_id = "0123456789abcdef0123456789abcdef";
+        let source = EXAMPLE_CONFIG.replace("{{SPOTIFY_CLIENT_ID}}", client_id);
+        let config = read(&source, &Overrides::default()).await.unwrap();
+        assert!(config.warnings.is_empty(), "{:?}", config.warnings);
+
+        // most sections do not implement `PartialEq`, their debug output is compared instead
+        macro_rules! assert_default {
+            ($section:ident, $default:expr) => {
+                assert_eq!(
+                    format!("{:#?}", config.$section),
+                    format!("{:#?}", $default),
+                    "`{}` of the example config differs from the defaults",
+                    stringify!($section)
+                );
+            };
         }
+        assert_default!(
+            api,
+            ApiConfiguration {
+                spotify_client_id: client_id.to_owned(),
+                ..Default::default()
+            }
+        );
+        assert_default!(general, GeneralConfiguration::default());
+        assert_default!(theme, ThemeConfiguration::default());
+        assert_default!(notifications, NotificationConfiguration::default());
+        assert_default!(hooks, HooksConfiguration::default());
+        assert_default!(plugins, PluginConfiguration::default());
+        assert_default!(keys, KeysConfiguration::default());
+        assert_default!(layout, LayoutConfiguration::default());
+        assert_default!(playback, PlaybackConfiguration::default());
+        assert_default!(mouse, MouseConfiguration::default());
+        assert_default!(album_art, AlbumArtConfiguration::default());
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_templates() {
+        let source = r#"
+            [general.display]
+            name_format = "{{ name"
+            [album_art]
+            url_format = "{{#if url}}{{ url }}"
+        "#;
+        let err = read(source, &Overrides::default()).await.unwrap_err();
+        let errors = err.downcast::<ConfigErrors>().unwrap().0;
+        let keys = errors
+            .iter()
+            .map(|it| it.key.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            [
+                Some("general.display.name_format"),
+                Some("album_art.url_format")
+            ]
+        );
+        assert_eq!(errors[0].line, Some(3));
+        assert!(errors[0].message.starts_with("Invalid template"));
+    }
+
+    #[tokio::test]
+    async fn layout_errors_point_at_the_nested_key() {
+        let source = r#"
+            [layout.root]
+            direction = "vertical"
+            children = [{ pane = "lyrics", size = "abc" }, { size = 3 }]
+        "#;
+        let err = read(source, &Overrides::default()).await.unwrap_err();
+        let errors = err.downcast::<ConfigErrors>().unwrap().0;
+        let keys = errors
+            .iter()
+            .map(|it| it.key.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            [
+                Some("layout.root.children[0].size"),
+                Some("layout.root.children[1]")
+            ]
+        );
+        assert!(errors[0].message.contains("Invalid size `abc`"));
+        assert!(errors[1].message.contains("either `pane` or `children`"));
+    }
+
+    #[tokio::test]
+    async fn reports_every_invalid_key_of_a_section() {
+        let source = r#"
+            [theme.lyrics]
+            active_text_color = "gren"
+            fade_distance = "far"
+            [theme.borders]
+            info_border_style = "rounde"
+        "#;
+        let err = read(source, &Overrides::default()).await.unwrap_err();
+        let errors = err.downcast::<ConfigErrors>().unwrap().0;
+        let keys = errors
+            .iter()
+            .map(|it| it.key.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            [
+                Some("theme.lyrics.active_text_color"),
+                Some("theme.lyrics.fade_distance"),
+                Some("theme.borders.info_border_style")
+            ]
+        );
+        assert_eq!(errors[0].suggestion.as_deref(), Some("green"));
     }
 }
@@ -0,0 +1,427 @@
+use std::{
+    fmt::Display,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use serde_path_to_error::Segment;
+use toml_edit::{Document, Item, TableLike};
+
+/// Suggestions are only made for candidates at least this similar
+const MIN_SIMILARITY: f64 = 0.8;
+
+/// Named colors, as accepted by ratatui
+pub const COLOR_NAMES: [&str; 17] = [
+    "reset",
+    "black",
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "magenta",
+    "cyan",
+    "gray",
+    "dark gray",
+    "light red",
+    "light green",
+    "light yellow",
+    "light blue",
+    "light magenta",
+    "light cyan",
+    "white",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem in the config, pointing at the place it was found at
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub file: PathBuf,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    /// Dotted path of the offending key, relative to the file
+    pub key: Option<String>,
+    pub message: String,
+    /// Type or values the key accepts
+    pub expected: Option<String>,
+    pub suggestion: Option<String>,
+}
+
+impl Diagnostic {
+    /// Diagnostic for a key of a file, located by parsing the file again
+    pub fn at_key(severity: Severity, file: &Path, source: &str, key: &[KeySegment]) -> Self {
+        let (line, column) = match locate(source, key) {
+            Some(span) => {
+                let (line, column) = line_column(source, span.start);
+                (Some(line), Some(column))
+            }
+            None => (None, None),
+        };
+        Self {
+            severity,
+            file: file.to_path_buf(),
+            line,
+            column,
+            key: (!key.is_empty()).then(|| key_path(key)),
+            message: String::new(),
+            expected: None,
+            suggestion: None,
+        }
+    }
+
+    /// Diagnostic for a TOML syntax error
+    pub fn syntax(file: &Path, source: &str, err: &toml::de::Error) -> Self {
+        let position = err.span().map(|it| line_column(source, it.start));
+        Self {
+            severity: Severity::Error,
+            file: file.to_path_buf(),
+            line: position.map(|it| it.0),
+            column: position.map(|it| it.1),
+            key: None,
+            message: err.message().to_owned(),
+            expected: None,
+            suggestion: None,
+        }
+    }
+
+    /// Diagnostic for an error while reading the file
+    pub fn io(file: &Path, err: &std::io::Error) -> Self {
+        Self {
+            severity: Severity::Error,
+            file: file.to_path_buf(),
+            line: None,
+            column: None,
+            key: None,
+            message: format!("Failed to read the file: {err}"),
+            expected: None,
+            suggestion: None,
+        }
+    }
+
+    pub fn message(mut self, message: impl Into<String>) -> Self {
+        self.message = message.into();
+        self
+    }
+
+    pub fn suggestion(mut self, suggestion: Option<String>) -> Self {
+        self.suggestion = suggestion;
+        self
+    }
+
+    /// Uses the message of a deserialization error, splitting off the expected type.
+    /// Misspelled values are matched against the expected ones
+    pub fn deserialize_error(mut self, message: &str, value: Option<&toml::Value>) -> Self {
+        let (message, expected) = match message.split_once(", expected ") {
+            Some((message, expected)) => (message, Some(expected.trim_end_matches('.'))),
+            None => (message, None),
+        };
+        self.message = message.to_owned();
+        self.expected = expected.map(str::to_owned);
+
+        let value = match (value.and_then(toml::Value::as_str), expected) {
+            (Some(value), Some(_)) => value,
+            _ => return self,
+        };
+        let expected = self.expected.as_deref().unwrap_or_default();
+        let candidates: Vec<&str> = if expected.contains("color") {
+            COLOR_NAMES.to_vec()
+        } else {
+            // `one of `a`, `b`` lists of enum variants
+            expected.split('`').skip(1).step_by(2).collect()
+        };
+        self.suggestion = did_you_mean(value, candidates);
+        self
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{severity}: {}", self.file.display())?;
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            write!(f, ":{line}:{column}")?;
+        }
+        if let Some(key) = &self.key {
+            write!(f, ": `{key}`")?;
+        }
+        write!(f, ": {}", self.message)?;
+        if let Some(expected) = &self.expected {
+            write!(f, ", expected {expected}")?;
+        }
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " (did you mean `{suggestion}`?)")?;
+        }
+        Ok(())
+    }
+}
+
+/// All errors found in the config
+#[derive(Debug)]
+pub struct ConfigErrors(pub Vec<Diagnostic>);
+
+impl Display for ConfigErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (idx, diagnostic) in self.0.iter().enumerate() {
+            if idx != 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{diagnostic}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+/// A part of a key path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeySegment {
+    Key(String),
+    Index(usize),
+}
+
+impl KeySegment {
+    /// Converts the path of a deserialization error
+    pub fn from_path(path: &serde_path_to_error::Path) -> Vec<Self> {
+        path.iter()
+            .filter_map(|it| match it {
+                Segment::Seq { index } => Some(KeySegment::Index(*index)),
+                Segment::Map { key } => Some(KeySegment::Key(key.clone())),
+                Segment::Enum { variant } => Some(KeySegment::Key(variant.clone())),
+                Segment::Unknown => None,
+            })
+            .collect()
+    }
+}
+
+pub fn key(parts: &[&str]) -> Vec<KeySegment> {
+    parts
+        .iter()
+        .map(|it| KeySegment::Key((*it).to_owned()))
+        .collect()
+}
+
+fn key_path(key: &[KeySegment]) -> String {
+    let mut out = String::new();
+    for segment in key {
+        match segment {
+            KeySegment::Key(key) if out.is_empty() => out.push_str(key),
+            KeySegment::Key(key) => {
+                out.push('.');
+                out.push_str(key);
+            }
+            KeySegment::Index(idx) => out.push_str(&format!("[{idx}]")),
+        }
+    }
+    out
+}
+
+/// Looks up the value at the key path
+pub fn value_at<'v>(value: &'v toml::Value, key: &[KeySegment]) -> Option<&'v toml::Value> {
+    key.iter().try_fold(value, |value, segment| match segment {
+        KeySegment::Key(key) => value.get(key),
+        KeySegment::Index(idx) => value.get(idx),
+    })
+}
+
+/// Removes the key at the path, returning whether it was found. Array elements are
+/// never removed, so later errors inside the array still point at the right index
+pub fn remove_at(value: &mut toml::Value, key: &[KeySegment]) -> bool {
+    let (last, parent) = match key.split_last() {
+        Some((KeySegment::Key(last), parent)) => (last, parent),
+        _ => return false,
+    };
+    let parent = parent
+        .iter()
+        .try_fold(value, |value, segment| match segment {
+            KeySegment::Key(key) => value.get_mut(key),
+            KeySegment::Index(idx) => value.get_mut(idx),
+        });
+    parent
+        .and_then(toml::Value::as_table_mut)
+        .and_then(|it| it.remove(last))
+        .is_some()
+}
+
+/// The most similar candidate, if any is similar enough
+pub fn did_you_mean<'c>(
+    value: &str,
+    candidates: impl IntoIterator<Item = &'c str>,
+) -> Option<String> {
+    candidates
+        .into_iter()
+        .map(|it| {
+            (
+                strsim::jaro_winkler(&value.to_lowercase(), &it.to_lowercase()),
+                it,
+            )
+        })
+        .filter(|(similarity, _)| *similarity >= MIN_SIMILARITY)
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, it)| it.to_owned())
+}
+
+/// Node of a parsed document, array elements and tables of arrays are not items
+#[derive(Clone, Copy)]
+enum Node<'d> {
+    Item(&'d Item),
+    Value(&'d toml_edit::Value),
+    Table(&'d toml_edit::Table),
+}
+
+impl<'d> Node<'d> {
+    fn child(self, segment: &KeySegment) -> Option<(Option<Range<usize>>, Node<'d>)> {
+        match segment {
+            KeySegment::Key(key) => {
+                let table: &dyn TableLike = match self {
+                    Node::Item(item) => item.as_table_like()?,
+                    Node::Value(value) => value.as_inline_table()?,
+                    Node::Table(table) => table,
+                };
+                let (found_key, found) = table.get_key_value(key)?;
+                Some((found_key.span(), Node::Item(found)))
+            }
+            KeySegment::Index(idx) => match self {
+                Node::Item(Item::ArrayOfTables(tables)) => {
+                    let table = tables.get(*idx)?;
+                    Some((table.span(), Node::Table(table)))
+                }
+                Node::Item(Item::Value(value)) | Node::Value(value) => {
+                    let value = value.as_array()?.get(*idx)?;
+                    Some((value.span(), Node::Value(value)))
+                }
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Span of the deepest key of the path that exists in the source
+fn locate(source: &str, key: &[KeySegment]) -> Option<Range<usize>> {
+    let document = Document::parse(source).ok()?;
+    let mut node = Node::Item(document.as_item());
+    let mut span = None;
+    for segment in key {
+        match node.child(segment) {
+            Some((found_span, found)) => {
+                span = found_span.or(span);
+                node = found;
+            }
+            None => break,
+        }
+    }
+    span
+}
+
+/// 1-based line and column of a byte offset
+fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rsplit('\n')
+        .next()
+        .map(|it| it.chars().count())
+        .unwrap_or(0)
+        + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::de::DeserializeOwned;
+
+    use super::*;
+    use crate::config::{BorderStyle, ThemeColor};
+
+    /// Diagnostic of deserializing `value` as `T`
+    fn value_error<T: DeserializeOwned>(value: &str) -> Diagnostic {
+        let value = toml::Value::String(value.to_owned());
+        let err = match value.clone().try_into::<T>() {
+            Ok(_) => panic!("{value} was accepted"),
+            Err(err) => err,
+        };
+        Diagnostic::at_key(Severity::Error, Path::new("config.toml"), "", &[])
+            .deserialize_error(err.message(), Some(&value))
+    }
+
+    #[test]
+    fn suggests_misspelled_keys() {
+        let keys = ["scroll_offset", "scroll_mode", "romanize_unicode"];
+        assert_eq!(
+            did_you_mean("scrol_offset", keys),
+            Some("scroll_offset".to_owned())
+        );
+        assert_eq!(did_you_mean("unrelated", keys), None);
+    }
+
+    #[test]
+    fn suggests_misspelled_colors() {
+        let diagnostic = value_error::<ThemeColor>("Light Gren");
+        assert_eq!(diagnostic.suggestion.as_deref(), Some("light green"));
+        assert!(diagnostic.expected.is_some());
+    }
+
+    #[test]
+    fn suggests_misspelled_border_styles() {
+        let diagnostic = value_error::<BorderStyle>("rounde");
+        assert_eq!(diagnostic.suggestion.as_deref(), Some("Rounded"));
+        assert_eq!(
+            diagnostic.expected.as_deref(),
+            Some("a border style, one of `Plain`, `Rounded`, `Double`, `Thick`")
+        );
+    }
+
+    #[test]
+    fn locates_keys() {
+        let source = "[general]\nscroll_offset = 4\n\n[[layout.root.children]]\npane = \"lyrics\"\n\
+            [[layout.root.children]]\npane = \"info\"\n[theme]\nlyrics = { fade_color = \"red\" }\n";
+        let position = |key: &[KeySegment]| {
+            let span = locate(source, key).unwrap();
+            line_column(source, span.start)
+        };
+
+        assert_eq!(position(&key(&["general", "scroll_offset"])), (2, 1));
+        assert_eq!(position(&key(&["theme", "lyrics", "fade_color"])), (9, 12));
+        let second_pane = [
+            key(&["layout", "root", "children"]),
+            vec![KeySegment::Index(1), KeySegment::Key("pane".to_owned())],
+        ]
+        .concat();
+        assert_eq!(position(&second_pane), (7, 1));
+        // missing keys point at the deepest existing one
+        assert_eq!(position(&key(&["general", "missing"])), (1, 2));
+        assert!(locate(source, &key(&["missing"])).is_none());
+    }
+
+    #[test]
+    fn removes_keys_but_not_array_elements() {
+        let mut value: toml::Value = toml::from_str("a = { b = 1, c = [{ d = 2 }] }").unwrap();
+        let nested = [
+            key(&["a", "c"]),
+            vec![KeySegment::Index(0), KeySegment::Key("d".to_owned())],
+        ]
+        .concat();
+        assert!(remove_at(&mut value, &nested));
+        assert!(!remove_at(&mut value, &nested));
+        assert!(!remove_at(
+            &mut value,
+            &[
+                KeySegment::Key("a".to_owned()),
+                KeySegment::Key("c".to_owned()),
+                KeySegment::Index(0)
+            ]
+        ));
+        assert!(remove_at(&mut value, &key(&["a", "b"])));
+        assert_eq!(value, toml::from_str("a = { c = [{}] }").unwrap());
+    }
+}
@@ -0,0 +1,399 @@
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::{anyhow, bail};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::{de::Visitor, Deserialize};
+
+/// Actions that can be bound to keys in the TUI
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyAction {
+    Quit,
+    ToggleAutoscroll,
+    ScrollDown,
+    ScrollUp,
+    ResetScroll,
+    ToggleHelp,
+    ToggleInfo,
+    ToggleProgress,
+    ToggleBorders,
+    Search,
+    NextMatch,
+    PreviousMatch,
+    ToggleKaraoke,
+    CycleProfile,
+}
+
+impl KeyAction {
+    pub fn description(self) -> &'static str {
+        match self {
+            KeyAction::Quit => "Quit",
+            KeyAction::ToggleAutoscroll => "Toggle autoscroll",
+            KeyAction::ScrollDown => "Scroll down",
+            KeyAction::ScrollUp => "Scroll up",
+            KeyAction::ResetScroll => "Reset scroll",
+            KeyAction::ToggleHelp => "Toggle help",
+            KeyAction::ToggleInfo => "Toggle info panel",
+            KeyAction::ToggleProgress => "Toggle progress bar",
+            KeyAction::ToggleBorders => "Toggle borders",
+            KeyAction::Search => "Search lyrics",
+            KeyAction::NextMatch => "Next search match",
+            KeyAction::PreviousMatch => "Previous search match",
+            KeyAction::ToggleKaraoke => "Toggle karaoke view",
+            KeyAction::CycleProfile => "Switch to the next profile",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct KeysConfiguration {
+    pub quit: KeyBinding,
+    pub toggle_autoscroll: KeyBinding,
+    pub scroll_down: KeyBinding,
+    pub scroll_up: KeyBinding,
+    pub reset_scroll: KeyBinding,
+    pub toggle_help: KeyBinding,
+    pub toggle_info: KeyBinding,
+    pub toggle_progress: KeyBinding,
+    pub toggle_borders: KeyBinding,
+    pub search: KeyBinding,
+    pub next_match: KeyBinding,
+    pub previous_match: KeyBinding,
+    pub toggle_karaoke: KeyBinding,
+    pub cycle_profile: KeyBinding,
+}
+
+impl Default for KeysConfiguration {
+    fn default() -> Self {
+        Self {
+            quit: KeyBinding::parse(&["q"]),
+            toggle_autoscroll: KeyBinding::parse(&["a"]),
+            scroll_down: KeyBinding::parse(&["j", "down"]),
+            scroll_up: KeyBinding::parse(&["k", "up"]),
+            reset_scroll: KeyBinding::parse(&["r"]),
+            toggle_help: KeyBinding::parse(&["?"]),
+            toggle_info: KeyBinding::parse(&["i"]),
+            toggle_progress: KeyBinding::parse(&["p"]),
+            toggle_borders: KeyBinding::parse(&["b"]),
+            search: KeyBinding::parse(&["/"]),
+            next_match: KeyBinding::parse(&["n"]),
+            previous_match: KeyBinding::parse(&["N"]),
+            toggle_karaoke: KeyBinding::parse(&["K"]),
+            cycle_profile: KeyBinding::parse(&["P"]),
+        }
+    }
+}
+
+impl KeysConfiguration {
+    /// All bindings, in the order they are displayed in the TUI
+    pub fn bindings(&self) -> Vec<(KeyAction, &KeyBinding)> {
+        vec![
+            (KeyAction::Quit, &self.quit),
+            (KeyAction::ToggleAutoscroll, &self.toggle_autoscroll),
+            (KeyAction::ScrollDown, &self.scroll_down),
+            (KeyAction::ScrollUp, &self.scroll_up),
+            (KeyAction::ResetScroll, &self.reset_scroll),
+            (KeyAction::ToggleHelp, &self.toggle_help),
+            (KeyAction::ToggleInfo, &self.toggle_info),
+            (KeyAction::ToggleProgress, &self.toggle_progress),
+            (KeyAction::ToggleBorders, &self.toggle_borders),
+            (KeyAction::Search, &self.search),
+            (KeyAction::NextMatch, &self.next_match),
+            (KeyAction::PreviousMatch, &self.previous_match),
+            (KeyAction::ToggleKaraoke, &self.toggle_karaoke),
+            (KeyAction::CycleProfile, &self.cycle_profile),
+        ]
+    }
+
+    /// Finds the action bound to the pressed key
+    pub fn action_for(&self, event: &KeyEvent) -> Option<KeyAction> {
+        self.bindings()
+            .into_iter()
+            .find(|(_, binding)| binding.0.iter().any(|chord| chord.matches(event)))
+            .map(|(action, _)| action)
+    }
+
+    /// Makes sure that no key chord is bound to more than one action
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let bindings = self.bindings();
+        for (idx, (action, binding)) in bindings.iter().enumerate() {
+            for (other_action, other_binding) in &bindings[idx + 1..] {
+                if let Some(chord) = binding.0.iter().find(|it| other_binding.0.contains(it)) {
+                    bail!(
+                        "Key `{chord}` is bound to both `{}` and `{}`",
+                        action.description(),
+                        other_action.description()
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// One or more key chords, bound to a single action
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyBinding(pub Vec<KeyChord>);
+
+impl KeyBinding {
+    fn parse(chords: &[&str]) -> Self {
+        Self(chords.iter().map(|it| it.parse().unwrap()).collect())
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyBinding {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum OneOrMany {
+            One(KeyChord),
+            Many(Vec<KeyChord>),
+        }
+
+        match OneOrMany::deserialize(deserializer)? {
+            OneOrMany::One(chord) => Ok(KeyBinding(vec![chord])),
+            OneOrMany::Many(chords) if chords.is_empty() => Err(serde::de::Error::custom(
+                "at least one key has to be bound to an action",
+            )),
+            OneOrMany::Many(chords) => Ok(KeyBinding(chords)),
+        }
+    }
+}
+
+/// A key together with its modifiers, e.g. `ctrl+c`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyChord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    pub fn matches(&self, event: &KeyEvent) -> bool {
+        let other = KeyChord::normalized(event.code, event.modifiers);
+        self.code == other.code && self.modifiers == other.modifiers
+    }
+
+    /// Shift is already reflected in the character case,
+    /// so it is only kept for non-character keys
+    fn normalized(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let modifiers =
+            modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        match code {
+            KeyCode::Char(_) | KeyCode::BackTab => Self {
+                code,
+                modifiers: modifiers - KeyModifiers::SHIFT,
+            },
+            _ => Self { code, modifiers },
+        }
+    }
+}
+
+impl FromStr for KeyChord {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // `+` itself can be bound too
+        let (modifiers, key) = match s.rsplit_once('+') {
+            Some((modifiers, "")) => (modifiers.strip_suffix('+').unwrap_or(modifiers), "+"),
+            Some((modifiers, key)) => (modifiers, key),
+            None => ("", s),
+        };
+
+        let mut parsed_modifiers = KeyModifiers::NONE;
+        for modifier in modifiers.split('+').filter(|it| !it.is_empty()) {
+            parsed_modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                other => bail!("Unknown key modifier `{other}`"),
+            }
+        }
+
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(char), None) => {
+                if parsed_modifiers.contains(KeyModifiers::SHIFT) {
+                    KeyCode::Char(char.to_ascii_uppercase())
+                } else {
+                    KeyCode::Char(char)
+                }
+            }
+            _ => match key.to_lowercase().as_str() {
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "enter" => KeyCode::Enter,
+                "esc" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "backspace" => KeyCode::Backspace,
+                "delete" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "space" => KeyCode::Char(' '),
+                other => match other.strip_prefix('f').and_then(|it| it.parse::<u8>().ok()) {
+                    Some(num) if (1..=12).contains(&num) => KeyCode::F(num),
+                    _ => return Err(anyhow!("Unknown key `{key}`")),
+                },
+            },
+        };
+
+        Ok(KeyChord::normalized(code, parsed_modifiers))
+    }
+}
+
+impl Display for KeyChord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "alt+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "shift+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "space"),
+            KeyCode::Char(char) => write!(f, "{char}"),
+            KeyCode::F(num) => write!(f, "f{num}"),
+            KeyCode::Up => write!(f, "up"),
+            KeyCode::Down => write!(f, "down"),
+            KeyCode::Left => write!(f, "left"),
+            KeyCode::Right => write!(f, "right"),
+            KeyCode::PageUp => write!(f, "pageup"),
+            KeyCode::PageDown => write!(f, "pagedown"),
+            KeyCode::Home => write!(f, "home"),
+            KeyCode::End => write!(f, "end"),
+            KeyCode::Enter => write!(f, "enter"),
+            KeyCode::Esc => write!(f, "esc"),
+            KeyCode::Tab => write!(f, "tab"),
+            KeyCode::BackTab => write!(f, "backtab"),
+            KeyCode::Backspace => write!(f, "backspace"),
+            KeyCode::Delete => write!(f, "delete"),
+            KeyCode::Insert => write!(f, "insert"),
+            other => write!(f, "{other:?}"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyChord {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_str(KeyChordVisitor)
+    }
+}
+
+struct KeyChordVisitor;
+
+impl<'v> Visitor<'v> for KeyChordVisitor {
+    type Value = KeyChord;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            formatter,
+            "a string representing a key, e.g. `q` or `ctrl+c`"
+        )
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        v.parse::<KeyChord>()
+            .map_err(|e| serde::de::Error::custom(format!("Invalid key: {e}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::KeyEventKind;
+
+    use super::*;
+
+    fn chord(s: &str) -> KeyChord {
+        s.parse().unwrap()
+    }
+
+    fn press(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        }
+    }
+
+    #[test]
+    fn shift_is_folded_into_characters() {
+        assert_eq!(chord("N"), chord("shift+n"));
+        assert_ne!(chord("N"), chord("n"));
+        assert_eq!(chord("shift+n").to_string(), "N");
+        // terminals report shift alongside uppercase characters
+        assert!(chord("N").matches(&press(KeyCode::Char('N'), KeyModifiers::SHIFT)));
+        assert!(chord("N").matches(&press(KeyCode::Char('N'), KeyModifiers::NONE)));
+        assert!(!chord("n").matches(&press(KeyCode::Char('N'), KeyModifiers::SHIFT)));
+    }
+
+    #[test]
+    fn shift_is_kept_for_other_keys() {
+        assert_ne!(chord("shift+up"), chord("up"));
+        assert_eq!(chord("shift+up").to_string(), "shift+up");
+    }
+
+    #[test]
+    fn parses_modifiers() {
+        let ctrl_c = chord("ctrl+c");
+        assert_eq!(ctrl_c, chord("Control+c"));
+        assert_eq!(ctrl_c.to_string(), "ctrl+c");
+        assert!(ctrl_c.matches(&press(KeyCode::Char('c'), KeyModifiers::CONTROL)));
+        assert!(!ctrl_c.matches(&press(KeyCode::Char('c'), KeyModifiers::NONE)));
+        assert_eq!(chord("alt++").to_string(), "alt++");
+        assert_eq!(chord("+").to_string(), "+");
+    }
+
+    #[test]
+    fn parses_named_keys() {
+        assert_eq!(chord("space").to_string(), "space");
+        assert_eq!(chord("PageDown").to_string(), "pagedown");
+        assert_eq!(chord("f12").to_string(), "f12");
+        assert!("f13".parse::<KeyChord>().is_err());
+        assert!("hyper+x".parse::<KeyChord>().is_err());
+        assert!("nope".parse::<KeyChord>().is_err());
+    }
+
+    #[test]
+    fn default_bindings_are_valid() {
+        KeysConfiguration::default().validate().unwrap();
+    }
+
+    #[test]
+    fn rejects_duplicates_across_actions() {
+        let keys = KeysConfiguration {
+            search: KeyBinding::parse(&["/", "ctrl+f"]),
+            next_match: KeyBinding::parse(&["n", "ctrl+f"]),
+            ..Default::default()
+        };
+        let err = keys.validate().unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "Key `ctrl+f` is bound to both `Search lyrics` and `Next search match`"
+        );
+
+        // `N` and `shift+n` are the same chord
+        let keys = KeysConfiguration {
+            toggle_karaoke: KeyBinding::parse(&["shift+n"]),
+            ..Default::default()
+        };
+        assert!(keys.validate().is_err());
+    }
+}
@@ -0,0 +1,481 @@
+use std::borrow::Cow;
+
+use anyhow::bail;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use serde::{de::Visitor, Deserialize};
+
+use super::diagnostics::KeySegment;
+
+/// Panes that can be placed in the TUI layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaneKind {
+    Lyrics,
+    Info,
+    Progress,
+    Status,
+    AlbumArt,
+}
+
+/// Panes that were removed. Layouts still using them are loaded without these panes
+const REMOVED_PANES: [&str; 1] = ["controls"];
+
+/// Removes panes that no longer exist from the layout section,
+/// returning their keys relative to the section
+pub fn remove_old_panes(layout: &mut toml::Value) -> Vec<Vec<KeySegment>> {
+    let mut removed = Vec::new();
+    let layout = match layout.as_table_mut() {
+        Some(layout) => layout,
+        None => return removed,
+    };
+    let root = match layout.get_mut("root") {
+        Some(root) => root,
+        None => return removed,
+    };
+    let mut key = vec![KeySegment::Key("root".to_owned())];
+    if is_removed_pane(root) {
+        layout.remove("root");
+        removed.push(key);
+    } else {
+        remove_old_children(root, &mut key, &mut removed);
+    }
+    removed
+}
+
+fn is_removed_pane(node: &toml::Value) -> bool {
+    node.get("pane")
+        .and_then(toml::Value::as_str)
+        .is_some_and(|it| REMOVED_PANES.contains(&it))
+}
+
+fn remove_old_children(
+    node: &mut toml::Value,
+    key: &mut Vec<KeySegment>,
+    removed: &mut Vec<Vec<KeySegment>>,
+) {
+    let children = match node.get_mut("children").and_then(toml::Value::as_array_mut) {
+        Some(children) => children,
+        None => return,
+    };
+    key.push(KeySegment::Key("children".to_owned()));
+    // keys point at the original indices, so they match the config file
+    let mut idx = 0;
+    children.retain_mut(|child| {
+        key.push(KeySegment::Index(idx));
+        idx += 1;
+        let keep = !is_removed_pane(child);
+        if keep {
+            remove_old_children(child, key, removed);
+        } else {
+            removed.push(key.clone());
+        }
+        key.pop();
+        keep
+    });
+    key.pop();
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LayoutPreset {
+    /// Lyrics on the left, album art and track info on the right, progress bar at the bottom
+    #[default]
+    Default,
+    /// Only the lyrics, taking the whole screen
+    LyricsOnly,
+    /// All panes stacked on top of each other, for narrow terminals
+    Vertical,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LayoutConfiguration {
+    pub preset: LayoutPreset,
+    /// Custom layout tree, takes priority over the preset
+    pub root: Option<LayoutNode>,
+    pub responsive: ResponsiveConfiguration,
+}
+
+/// Breakpoints, below which the layout is compacted
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ResponsiveConfiguration {
+    pub enabled: bool,
+    /// Side panels (info placed next to other panes)
+    /// are hidden below this terminal width
+    pub min_width: u16,
+    /// Progress bar is moved into the lyrics title below this terminal height
+    pub min_height: u16,
+}
+
+impl Default for ResponsiveConfiguration {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_width: 80,
+            min_height: 20,
+        }
+    }
+}
+
+/// Breakpoints reached by the current terminal size
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Breakpoints {
+    pub narrow: bool,
+    pub short: bool,
+}
+
+/// Everything that can hide a pane while splitting the layout
+struct SplitContext<'a> {
+    breakpoints: Breakpoints,
+    /// Panes hidden by the user at runtime
+    hidden: &'a [PaneKind],
+}
+
+impl SplitContext<'_> {
+    /// `beside` is whether the pane is placed next to other panes horizontally
+    fn hides(&self, pane: PaneKind, beside: bool) -> bool {
+        if self.hidden.contains(&pane) {
+            return true;
+        }
+        match pane {
+            PaneKind::Info => self.breakpoints.narrow && beside,
+            PaneKind::AlbumArt => self.breakpoints.short || (self.breakpoints.narrow && beside),
+            PaneKind::Progress => self.breakpoints.short,
+            PaneKind::Lyrics | PaneKind::Status => false,
+        }
+    }
+}
+
+impl LayoutConfiguration {
+    pub fn root(&self) -> Cow<'_, LayoutNode> {
+        match &self.root {
+            Some(root) => Cow::Borrowed(root),
+            None => Cow::Owned(self.preset.root()),
+        }
+    }
+
+    pub fn breakpoints(&self, area: Rect) -> Breakpoints {
+        if !self.responsive.enabled {
+            return Breakpoints::default();
+        }
+        Breakpoints {
+            narrow: area.width < self.responsive.min_width,
+            short: area.height < self.responsive.min_height,
+        }
+    }
+
+    /// Splits the area between all visible panes.
+    /// Panes that ended up with no space are skipped
+    pub fn split(&self, area: Rect, hidden: &[PaneKind]) -> Vec<(PaneKind, Rect)> {
+        let mut panes = Vec::with_capacity(6);
+        let ctx = SplitContext {
+            breakpoints: self.breakpoints(area),
+            hidden,
+        };
+        self.root().split_into(area, &ctx, false, &mut panes);
+        panes.retain(|(_, area)| area.width > 0 && area.height > 0);
+        panes
+    }
+
+    /// Makes sure that each pane is used at most once, and no split is empty
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let mut seen = Vec::with_capacity(6);
+        self.root().validate(&mut seen)
+    }
+}
+
+impl LayoutPreset {
+    fn root(self) -> LayoutNode {
+        match self {
+            LayoutPreset::Default => LayoutNode::split(
+                Direction::Vertical,
+                Constraint::Min(0),
+                vec![
+                    LayoutNode::split(
+                        Direction::Horizontal,
+                        Constraint::Percentage(95),
+                        vec![
+                            LayoutNode::pane(PaneKind::Lyrics, Constraint::Percentage(75)),
+                            LayoutNode::split(
+                                Direction::Vertical,
+                                Constraint::Percentage(25),
+                                vec![
+                                    LayoutNode::pane(
+                                        PaneKind::AlbumArt,
+                                        Constraint::Percentage(50),
+                                    ),
+                                    LayoutNode::pane(PaneKind::Info, Constraint::Min(0)),
+                                ],
+                            ),
+                        ],
+                    ),
+                    LayoutNode::pane(PaneKind::Progress, Constraint::Percentage(5)),
+                ],
+            ),
+            LayoutPreset::LyricsOnly => LayoutNode::pane(PaneKind::Lyrics, Constraint::Min(0)),
+            LayoutPreset::Vertical => LayoutNode::split(
+                Direction::Vertical,
+                Constraint::Min(0),
+                vec![
+                    LayoutNode::pane(PaneKind::Status, Constraint::Length(1)),
+                    LayoutNode::pane(PaneKind::Lyrics, Constraint::Min(0)),
+                    LayoutNode::pane(PaneKind::Info, Constraint::Percentage(30)),
+                    LayoutNode::pane(PaneKind::Progress, Constraint::Length(1)),
+                ],
+            ),
+        }
+    }
+}
+
+/// Either a single pane, or a split of several nodes in one direction
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "RawLayoutNode")]
+pub enum LayoutNode {
+    Pane {
+        pane: PaneKind,
+        #[serde(default)]
+        size: SizeConstraint,
+        #[serde(default = "visible_by_default")]
+        visible: bool,
+    },
+    Split {
+        direction: SplitDirection,
+        #[serde(default)]
+        size: SizeConstraint,
+        #[serde(default = "visible_by_default")]
+        visible: bool,
+        children: Vec<LayoutNode>,
+    },
+}
+
+fn visible_by_default() -> bool {
+    true
+}
+
+/// Fields of both node kinds. Nodes are told apart by `pane` or `children` after
+/// deserializing, so errors of the fields keep pointing at the right key
+#[derive(Deserialize)]
+struct RawLayoutNode {
+    pane: Option<PaneKind>,
+    direction: Option<SplitDirection>,
+    #[serde(default)]
+    size: SizeConstraint,
+    #[serde(default = "visible_by_default")]
+    visible: bool,
+    children: Option<Vec<LayoutNode>>,
+}
+
+impl TryFrom<RawLayoutNode> for LayoutNode {
+    type Error = String;
+
+    fn try_from(raw: RawLayoutNode) -> Result<Self, Self::Error> {
+        match (raw.pane, raw.children) {
+            (Some(pane), None) => Ok(LayoutNode::Pane {
+                pane,
+                size: raw.size,
+                visible: raw.visible,
+            }),
+            (None, Some(children)) => Ok(LayoutNode::Split {
+                direction: raw
+                    .direction
+                    .ok_or("missing field `direction`, required next to `children`")?,
+                size: raw.size,
+                visible: raw.visible,
+                children,
+            }),
+            (Some(_), Some(_)) => {
+                Err("a layout node has either `pane` or `children`, not both".to_owned())
+            }
+            (None, None) => Err("a layout node needs either `pane` or `children`".to_owned()),
+        }
+    }
+}
+
+impl LayoutNode {
+    fn pane(pane: PaneKind, size: Constraint) -> Self {
+        LayoutNode::Pane {
+            pane,
+            size: SizeConstraint(size),
+            visible: true,
+        }
+    }
+
+    fn split(direction: Direction, size: Constraint, children: Vec<LayoutNode>) -> Self {
+        LayoutNode::Split {
+            direction: SplitDirection(direction),
+            size: SizeConstraint(size),
+            visible: true,
+            children,
+        }
+    }
+
+    fn size(&self) -> Constraint {
+        match self {
+            LayoutNode::Pane { size, .. } | LayoutNode::Split { size, .. } => size.0,
+        }
+    }
+
+    /// Whether this node has at least a single visible pane
+    fn is_visible(&self, ctx: &SplitContext, beside: bool) -> bool {
+        match self {
+            LayoutNode::Pane { pane, visible, .. } => *visible && !ctx.hides(*pane, beside),
+            LayoutNode::Split {
+                direction,
+                visible,
+                children,
+                ..
+            } => {
+                let beside = beside || direction.0 == Direction::Horizontal;
+                *visible && children.iter().any(|it| it.is_visible(ctx, beside))
+            }
+        }
+    }
+
+    fn split_into(
+        &self,
+        area: Rect,
+        ctx: &SplitContext,
+        beside: bool,
+        panes: &mut Vec<(PaneKind, Rect)>,
+    ) {
+        if !self.is_visible(ctx, beside) {
+            return;
+        }
+        match self {
+            LayoutNode::Pane { pane, .. } => panes.push((*pane, area)),
+            LayoutNode::Split {
+                direction,
+                children,
+                ..
+            } => {
+                let beside = beside || direction.0 == Direction::Horizontal;
+                // hidden panes give their space to the visible ones
+                let visible = children
+                    .iter()
+                    .filter(|it| it.is_visible(ctx, beside))
+                    .collect::<Vec<_>>();
+                let areas = Layout::default()
+                    .direction(direction.0)
+                    .constraints(visible.iter().map(|it| it.size()).collect::<Vec<_>>())
+                    .split(area);
+                for (child, child_area) in visible.into_iter().zip(areas.iter()) {
+                    child.split_into(*child_area, ctx, beside, panes);
+                }
+            }
+        }
+    }
+
+    fn validate(&self, seen: &mut Vec<PaneKind>) -> anyhow::Result<()> {
+        match self {
+            LayoutNode::Pane { pane, .. } => {
+                if seen.contains(pane) {
+                    bail!("Pane `{pane:?}` is used more than once in the layout");
+                }
+                seen.push(*pane);
+            }
+            LayoutNode::Split { children, .. } => {
+                if children.is_empty() {
+                    bail!("Layout split has no children");
+                }
+                for child in children {
+                    child.validate(seen)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+#[repr(transparent)]
+pub struct SplitDirection(pub Direction);
+
+impl<'de> Deserialize<'de> for SplitDirection {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        match String::deserialize(deserializer)?.as_str() {
+            "horizontal" => Ok(SplitDirection(Direction::Horizontal)),
+            "vertical" => Ok(SplitDirection(Direction::Vertical)),
+            other => Err(serde::de::Error::custom(format!(
+                "Invalid split direction `{other}`, expected `horizontal` or `vertical`"
+            ))),
+        }
+    }
+}
+
+/// Size of a layout node. Can be a percentage (`"75%"`), an exact
+/// amount of cells (`10`), at least (`"min:10"`) or at most (`"max:10"`)
+/// an amount of cells, or `"fill"` to take all remaining space
+#[derive(Debug, Clone)]
+#[repr(transparent)]
+pub struct SizeConstraint(pub Constraint);
+
+impl Default for SizeConstraint {
+    fn default() -> Self {
+        SizeConstraint(Constraint::Min(0))
+    }
+}
+
+impl<'de> Deserialize<'de> for SizeConstraint {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer
+            .deserialize_any(SizeVisitor)
+            .map(SizeConstraint)
+    }
+}
+
+struct SizeVisitor;
+
+impl<'v> Visitor<'v> for SizeVisitor {
+    type Value = Constraint;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            formatter,
+            "a size, e.g. `10`, `\"75%\"`, `\"min:10\"`, `\"max:10\"` or `\"fill\"`"
+        )
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        u16::try_from(v)
+            .map(Constraint::Length)
+            .map_err(|_| serde::de::Error::custom(format!("Invalid size {v}")))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        let parse = |num: &str| {
+            num.trim()
+                .parse::<u16>()
+                .map_err(|e| serde::de::Error::custom(format!("Invalid size `{v}`: {e}")))
+        };
+        if v == "fill" {
+            Ok(Constraint::Min(0))
+        } else if let Some(percentage) = v.strip_suffix('%') {
+            let percentage = parse(percentage)?;
+            if percentage > 100 {
+                return Err(serde::de::Error::custom(format!(
+                    "Invalid size `{v}`: percentage can not be larger than 100"
+                )));
+            }
+            Ok(Constraint::Percentage(percentage))
+        } else if let Some(min) = v.strip_prefix("min:") {
+            Ok(Constraint::Min(parse(min)?))
+        } else if let Some(max) = v.strip_prefix("max:") {
+            Ok(Constraint::Max(parse(max)?))
+        } else {
+            Ok(Constraint::Length(parse(v)?))
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::HashSet;
+
+use toml_edit::{DocumentMut, Item, Table};
+
+use super::EXAMPLE_CONFIG;
+
+/// Keys that are allowed in any section
+const SPECIAL_KEYS: [&str; 2] = ["include", "preset"];
+
+/// Table of named config overrides, containing the same keys as the config
+const PROFILES_KEY: &str = "profiles";
+
+/// Keys that have no default and have to be filled in manually
+const REQUIRED_KEYS: [&str; 1] = ["api.spotify_client_id"];
+
+/// Changes made to an outdated config
+#[derive(Debug, Default)]
+pub struct MigrationReport {
+    /// Keys that were added with their default values
+    pub added: Vec<String>,
+    /// Required keys that are missing
+    pub required: Vec<String>,
+    /// Keys that verses does not use anymore, or never did
+    pub unknown: Vec<String>,
+}
+
+impl MigrationReport {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.required.is_empty() && self.unknown.is_empty()
+    }
+}
+
+/// Adds keys missing from the config, with the values and comments of the example config.
+/// Unknown keys are only reported, since they may be typos of keys the user wants to keep
+pub fn migrate(source: &str) -> anyhow::Result<(String, MigrationReport)> {
+    let mut document = source.parse::<DocumentMut>()?;
+    let example = EXAMPLE_CONFIG.parse::<DocumentMut>()?;
+    let mut report = MigrationReport::default();
+
+    // new sections are added after the existing ones
+    let mut next_position = max_position(document.as_table()) + 1;
+    fill_missing(
+        document.as_table_mut(),
+        example.as_table(),
+        "",
+        &mut next_position,
+        &mut report,
+    );
+
+    let table = toml::from_str::<toml::Table>(source)?;
+    report.unknown = unknown_keys(&table, &documented_keys());
+    Ok((document.to_string(), report))
+}
+
+fn fill_missing(
+    table: &mut Table,
+    example: &Table,
+    prefix: &str,
+    next_position: &mut isize,
+    report: &mut MigrationReport,
+) {
+    // included sections and presets are complete on their own
+    if SPECIAL_KEYS.iter().any(|it| table.contains_key(it)) {
+        return;
+    }
+    for (key, example_item) in example.iter() {
+        let path = join_path(prefix, key);
+        match table.get_mut(key) {
+            Some(Item::Table(table)) => {
+                if let Item::Table(example) = example_item {
+                    fill_missing(table, example, &path, next_position, report);
+                }
+            }
+            Some(_) => {}
+            None if REQUIRED_KEYS.contains(&path.as_str()) => report.required.push(path),
+            None => {
+                let mut item = example_item.clone();
+                if let Item::Table(table) = &mut item {
+                    remove_required(table, &path, report);
+                    renumber(table, next_position);
+                }
+                table.insert(key, item);
+                report.added.push(path);
+            }
+        }
+    }
+}
+
+/// Removes the placeholders of required keys from a table copied from the example config
+fn remove_required(table: &mut Table, prefix: &str, report: &mut MigrationReport) {
+    let keys = table
+        .iter()
+        .map(|(key, _)| key.to_owned())
+        .collect::<Vec<_>>();
+    for key in keys {
+        let path = join_path(prefix, &key);
+        if REQUIRED_KEYS.contains(&path.as_str()) {
+            table.remove(&key);
+            report.required.push(path);
+        } else if let Some(Item::Table(table)) = table.get_mut(&key) {
+            remove_required(table, &path, report);
+        }
+    }
+}
+
+/// Dotted paths of keys that are not documented. Profiles can contain any documented key
+pub(super) fn unknown_keys(table: &toml::Table, documented: &HashSet<String>) -> Vec<String> {
+    let mut unknown = Vec::new();
+    find_unknown_keys(table, "", documented, &mut unknown);
+    let profiles = table.get(PROFILES_KEY).and_then(toml::Value::as_table);
+    for (name, profile) in profiles.into_iter().flatten() {
+        if let Some(profile) = profile.as_table() {
+            let mut found = Vec::new();
+            find_unknown_keys(profile, "", documented, &mut found);
+            unknown.extend(found.iter().map(|it| format!("{PROFILES_KEY}.{name}.{it}")));
+        }
+    }
+    unknown
+}
+
+fn find_unknown_keys(
+    table: &toml::Table,
+    prefix: &str,
+    documented: &HashSet<String>,
+    unknown: &mut Vec<String>,
+) {
+    for (key, item) in table.iter() {
+        if prefix.is_empty() && key == PROFILES_KEY {
+            continue;
+        }
+        let path = join_path(prefix, key);
+        if !documented.contains(&path) && !SPECIAL_KEYS.contains(&key.as_str()) {
+            unknown.push(path);
+            continue;
+        }
+        if let Some(table) = item.as_table() {
+            find_unknown_keys(table, &path, documented, unknown);
+        }
+    }
+}
+
+/// Keys set or mentioned in comments of the example config, with their sections
+pub(super) fn documented_keys() -> HashSet<String> {
+    let mut keys = HashSet::new();
+    let mut section = String::new();
+    for line in EXAMPLE_CONFIG.lines() {
+        let line = line.trim_start_matches('#').trim();
+        if let Some(header) = line.strip_prefix('[').and_then(|it| it.strip_suffix(']')) {
+            section = header.to_owned();
+            // parent sections of nested ones
+            let mut path = String::new();
+            for part in section.split('.') {
+                path = join_path(&path, part);
+                keys.insert(path.clone());
+            }
+        } else if let Some((key, _)) = line.split_once('=') {
+            let key = key.trim();
+            if !key.is_empty()
+                && key
+                    .chars()
+                    .all(|it| it.is_ascii_lowercase() || it.is_ascii_digit() || it == '_')
+            {
+                keys.insert(join_path(&section, key));
+            }
+        }
+    }
+    keys
+}
+
+fn max_position(table: &Table) -> isize {
+    table
+        .iter()
+        .filter_map(|(_, item)| item.as_table())
+        .map(|it| it.position().unwrap_or(0).max(max_position(it)))
+        .max()
+        .unwrap_or(0)
+}
+
+/// Moves the table and its subtables to the end of the document
+fn renumber(table: &mut Table, next_position: &mut isize) {
+    table.set_position(Some(*next_position));
+    *next_position += 1;
+    for (_, item) in table.iter_mut() {
+        if let Item::Table(table) = item {
+            renumber(table, next_position);
+        }
+    }
+}
+
+fn join_path(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{prefix}.{key}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn does_not_copy_required_placeholders() {
+        let (migrated, report) = migrate("[general]\nscroll_offset = 6\n").unwrap();
+        assert!(!migrated.contains("{{SPOTIFY_CLIENT_ID}}"));
+        assert!(migrated.contains("[api]"));
+        assert_eq!(report.required, vec!["api.spotify_client_id"]);
+        assert!(report.added.contains(&"api".to_owned()));
+    }
+
+    #[test]
+    fn keeps_existing_keys() {
+        let source = "[api]\nspotify_client_id = \"0123456789abcdef0123456789abcdef\"\n";
+        let (migrated, report) = migrate(source).unwrap();
+        assert!(migrated.starts_with(source));
+        assert!(report.required.is_empty());
+        assert!(report.unknown.is_empty());
+    }
+
+    #[test]
+    fn reports_unknown_keys() {
+        let (_, report) = migrate("[general]\nscroll_ofset = 6\n").unwrap();
+        assert_eq!(report.unknown, vec!["general.scroll_ofset"]);
+    }
+}
@@ -0,0 +1,231 @@
+use std::ffi::OsString;
+
+use anyhow::anyhow;
+
+/// Prefix of environment variables that set config keys
+const ENV_PREFIX: &str = "VERSES_";
+
+/// Separates the parts of a key in environment variables, since keys contain `_` themselves
+const ENV_SEPARATOR: &str = "__";
+
+/// A config key set from the command line or an environment variable
+#[derive(Debug, Clone)]
+pub struct Override {
+    /// Where the value comes from, shown in diagnostics instead of a file
+    pub origin: String,
+    pub key: Vec<String>,
+    pub value: toml::Value,
+}
+
+impl Override {
+    /// Sets the key in the parsed config, creating missing tables on the way
+    pub fn apply(&self, root: &mut toml::Table) -> anyhow::Result<()> {
+        let (last, parents) = self
+            .key
+            .split_last()
+            .ok_or_else(|| anyhow!("the key is empty"))?;
+        let mut table = root;
+        for part in parents {
+            table = table
+                .entry(part.clone())
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                .as_table_mut()
+                .ok_or_else(|| anyhow!("`{part}` is not a table"))?;
+        }
+        table.insert(last.clone(), self.value.clone());
+        Ok(())
+    }
+}
+
+/// Keys layered on top of the config file, later ones take precedence
+#[derive(Debug, Clone, Default)]
+pub struct Overrides(pub Vec<Override>);
+
+impl Overrides {
+    /// Collects `VERSES_GENERAL__SCROLL_OFFSET=6` environment variables,
+    /// followed by `general.scroll_offset=6` arguments of `--set`
+    pub fn collect(set: &[String]) -> anyhow::Result<Self> {
+        Self::from_env()?.with_args(set)
+    }
+
+    fn from_env() -> anyhow::Result<Self> {
+        Self::from_vars(std::env::vars_os())
+    }
+
+    fn from_vars(vars: impl Iterator<Item = (OsString, OsString)>) -> anyhow::Result<Self> {
+        let mut overrides = Vec::new();
+        for (name, value) in vars {
+            let name = match name.to_str() {
+                Some(name) => name,
+                None => continue,
+            };
+            let key = match name.strip_prefix(ENV_PREFIX) {
+                // hooks export variables like `VERSES_TRACK_NAME`, these are not config keys
+                Some(key) if key.contains(ENV_SEPARATOR) => key.to_lowercase(),
+                _ => continue,
+            };
+            let value = value
+                .into_string()
+                .map_err(|_| anyhow!("`${name}` is not valid unicode"))?;
+            overrides.push(Override {
+                origin: format!("${name}"),
+                key: split_key(&key, ENV_SEPARATOR)
+                    .ok_or_else(|| anyhow!("`${name}` does not name a config key"))?,
+                value: parse_value(&value),
+            });
+        }
+        // the order of environment variables is not meaningful
+        overrides.sort_by(|a, b| a.origin.cmp(&b.origin));
+        Ok(Self(overrides))
+    }
+
+    fn with_args(mut self, set: &[String]) -> anyhow::Result<Self> {
+        for arg in set {
+            let (key, value) = arg
+                .split_once('=')
+                .ok_or_else(|| anyhow!("`--set {arg}` is not in the `key=value` format"))?;
+            let key = key.trim();
+            self.0.push(Override {
+                origin: "--set".to_owned(),
+                key: split_key(key, ".")
+                    .ok_or_else(|| anyhow!("`--set {arg}` does not name a config key"))?,
+                value: parse_value(value.trim()),
+            });
+        }
+        Ok(self)
+    }
+}
+
+fn split_key(key: &str, separator: &str) -> Option<Vec<String>> {
+    let parts = key
+        .split(separator)
+        .map(str::to_owned)
+        .collect::<Vec<String>>();
+    (!parts.iter().any(String::is_empty)).then_some(parts)
+}
+
+/// Values are parsed as TOML, anything else is used as a string,
+/// so `--set general.scroll_mode=center` does not need quotes
+fn parse_value(value: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("value = {value}"))
+        .ok()
+        .filter(|it| it.len() == 1)
+        .and_then(|mut it| it.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> impl Iterator<Item = (OsString, OsString)> {
+        vars.iter()
+            .map(|(name, value)| (OsString::from(name), OsString::from(value)))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn apply(overrides: &Overrides) -> toml::Table {
+        let mut root = toml::Table::new();
+        for it in &overrides.0 {
+            it.apply(&mut root).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn env_keys_need_the_separator() {
+        let overrides = Overrides::from_vars(vars(&[
+            ("VERSES_GENERAL__SCROLL_OFFSET", "6"),
+            ("VERSES_THEME__LYRICS__FADE_DISTANCE", "3"),
+            // exported to hooks, not config keys
+            ("VERSES_TRACK_NAME", "Song"),
+            ("VERSES_LINE_INDEX", "4"),
+            ("HOME", "/home/user"),
+        ]))
+        .unwrap();
+        let keys = overrides
+            .0
+            .iter()
+            .map(|it| it.key.join("."))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec!["general.scroll_offset", "theme.lyrics.fade_distance"]
+        );
+        assert_eq!(overrides.0[0].origin, "$VERSES_GENERAL__SCROLL_OFFSET");
+    }
+
+    #[test]
+    fn rejects_empty_key_parts() {
+        assert!(Overrides::from_vars(vars(&[("VERSES_GENERAL____X", "1")])).is_err());
+        assert!(Overrides::default()
+            .with_args(&["general..scroll_offset=1".to_owned()])
+            .is_err());
+        assert!(Overrides::default()
+            .with_args(&["general.scroll_offset".to_owned()])
+            .is_err());
+    }
+
+    #[test]
+    fn parses_toml_values() {
+        assert_eq!(parse_value("6"), toml::Value::Integer(6));
+        assert_eq!(parse_value("true"), toml::Value::Boolean(true));
+        assert_eq!(
+            parse_value("\"quoted\""),
+            toml::Value::String("quoted".to_owned())
+        );
+        assert_eq!(
+            parse_value("[\"de\", \"fr\"]"),
+            toml::Value::Array(vec!["de".into(), "fr".into()])
+        );
+    }
+
+    #[test]
+    fn falls_back_to_bare_strings() {
+        assert_eq!(
+            parse_value("center"),
+            toml::Value::String("center".to_owned())
+        );
+        assert_eq!(
+            parse_value("#ff0000"),
+            toml::Value::String("#ff0000".to_owned())
+        );
+        assert_eq!(parse_value(""), toml::Value::String(String::new()));
+        // a valid value followed by another key is not a single value
+        assert_eq!(
+            parse_value("1\nother = 2"),
+            toml::Value::String("1\nother = 2".to_owned())
+        );
+    }
+
+    #[test]
+    fn later_overrides_take_precedence() {
+        let overrides = Overrides::from_vars(vars(&[
+            ("VERSES_GENERAL__SCROLL_OFFSET", "6"),
+            ("VERSES_GENERAL__SCROLL_MODE", "center"),
+        ]))
+        .unwrap()
+        .with_args(&[
+            "general.scroll_offset=8".to_owned(),
+            "general.scroll_offset = 10".to_owned(),
+        ])
+        .unwrap();
+        let root = apply(&overrides);
+        let general = root["general"].as_table().unwrap();
+        assert_eq!(general["scroll_offset"], toml::Value::Integer(10));
+        assert_eq!(
+            general["scroll_mode"],
+            toml::Value::String("center".to_owned())
+        );
+    }
+
+    #[test]
+    fn does_not_replace_values_with_tables() {
+        let mut root = toml::from_str::<toml::Table>("general = 1").unwrap();
+        let overrides = Overrides::default()
+            .with_args(&["general.scroll_offset=1".to_owned()])
+            .unwrap();
+        assert!(overrides.0[0].apply(&mut root).is_err());
+    }
+}
@@ -0,0 +1,211 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use arc_swap::{ArcSwap, ArcSwapOption};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+use super::{overrides::Overrides, VersesConfig};
+
+/// Editors often write a file in several steps, so changes are collected for a while before reloading
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Config shared between the dispatcher, the backend and the listeners
+pub type SharedConfig = Arc<LiveConfig>;
+
+/// Config that is replaced when its file changes or another profile is selected
+#[derive(Debug)]
+pub struct LiveConfig {
+    current: ArcSwap<VersesConfig>,
+    /// Error of the last reload, cleared after a successful one
+    error: ArcSwapOption<String>,
+    path: PathBuf,
+    overrides: Overrides,
+    /// Profile used by the next reload
+    profile: ArcSwapOption<String>,
+}
+
+impl LiveConfig {
+    /// Config that is reloaded from `path`, applying the same overrides and profile as `config`
+    pub fn new(config: VersesConfig, path: PathBuf, overrides: Overrides) -> SharedConfig {
+        let profile = config.profile.clone().map(Arc::new);
+        Arc::new(Self {
+            current: ArcSwap::from_pointee(config),
+            error: ArcSwapOption::empty(),
+            path,
+            overrides,
+            profile: ArcSwapOption::new(profile),
+        })
+    }
+
+    /// The current config. The returned config stays the same even if a reload happens
+    pub fn load(&self) -> Arc<VersesConfig> {
+        self.current.load_full()
+    }
+
+    /// Error of the last failed reload, while the previous config is still in use
+    pub fn error(&self) -> Option<Arc<String>> {
+        self.error.load_full()
+    }
+
+    /// Reads the config again. If it fails to parse, the old one is kept and the error is stored instead
+    pub async fn reload(&self) -> bool {
+        let profile = self.profile.load_full();
+        self.reload_with(profile.as_deref().map(String::as_str))
+            .await
+    }
+
+    async fn reload_with(&self, profile: Option<&str>) -> bool {
+        match VersesConfig::read_from_file(&self.path, &self.overrides, profile).await {
+            Ok(config) => {
+                self.current.store(Arc::new(config));
+                self.error.store(None);
+                true
+            }
+            Err(err) => {
+                self.error.store(Some(Arc::new(format!("{err:#}"))));
+                false
+            }
+        }
+    }
+
+    /// Switches to the next profile of the config, the last one is followed by the config without a profile
+    pub async fn cycle_profile(&self) {
+        let profiles = self.load().profiles.clone();
+        let next = match self.profile.load_full() {
+            Some(current) => profiles
+                .iter()
+                .skip_while(|it| **it != *current)
+                .nth(1)
+                .cloned(),
+            None => profiles.first().cloned(),
+        };
+        // the current profile stays in use if the next one fails to load
+        if self.reload_with(next.as_deref()).await {
+            self.profile.store(next.map(Arc::new));
+        }
+    }
+}
+
+/// Reloads the config whenever the config file or one of its includes changes
+pub fn spawn_watcher(config: SharedConfig) -> anyhow::Result<()> {
+    let (tx, rx) = flume::unbounded::<notify::Result<notify::Event>>();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = tx.send(event);
+    })?;
+    let mut watched = HashSet::new();
+    let mut watched_dirs = HashSet::new();
+    watch_files(
+        &mut watcher,
+        &config.path,
+        &config.load(),
+        &mut watched,
+        &mut watched_dirs,
+    )?;
+
+    tokio::task::spawn(async move {
+        while let Ok(event) = rx.recv_async().await {
+            if !is_relevant(event, &watched) {
+                continue;
+            }
+            tokio::time::sleep(DEBOUNCE).await;
+            rx.drain();
+
+            if config.reload().await {
+                // includes may have changed
+                let _ = watch_files(
+                    &mut watcher,
+                    &config.path,
+                    &config.load(),
+                    &mut watched,
+                    &mut watched_dirs,
+                );
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Watches the directories of the config and its includes, since
+/// editors usually replace files instead of writing into them
+fn watch_files(
+    watcher: &mut RecommendedWatcher,
+    path: &Path,
+    config: &VersesConfig,
+    watched: &mut HashSet<PathBuf>,
+    watched_dirs: &mut HashSet<PathBuf>,
+) -> anyhow::Result<()> {
+    watched.clear();
+    for file in std::iter::once(path).chain(config.includes.iter().map(PathBuf::as_path)) {
+        let file = normalize(file);
+        if let Some(dir) = file.parent() {
+            if !watched_dirs.contains(dir) {
+                watcher.watch(dir, RecursiveMode::NonRecursive)?;
+                watched_dirs.insert(dir.to_path_buf());
+            }
+        }
+        watched.insert(file);
+    }
+    Ok(())
+}
+
+fn is_relevant(event: notify::Result<notify::Event>, watched: &HashSet<PathBuf>) -> bool {
+    match event {
+        Ok(event) => {
+            !event.kind.is_access()
+                && event
+                    .paths
+                    .iter()
+                    .any(|it| watched.contains(&normalize(it)))
+        }
+        Err(_) => false,
+    }
+}
+
+/// Resolves `..` and symlinks in the directory, so paths from events match the watched ones
+fn normalize(path: &Path) -> PathBuf {
+    match (path.parent(), path.file_name()) {
+        (Some(dir), Some(name)) => dir
+            .canonicalize()
+            .unwrap_or_else(|_| dir.to_path_buf())
+            .join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn keeps_the_profile_if_the_next_one_fails_to_load() {
+        let dir = std::env::temp_dir().join(format!("verses-cycle-profile-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        let source = "[profiles.a.general]\nscroll_offset = 1\n\n[profiles.b.general]\nscroll_offset = \"many\"\n";
+        std::fs::write(&path, source).unwrap();
+
+        let overrides = Overrides::default();
+        let config = VersesConfig::read_from_file(&path, &overrides, None)
+            .await
+            .unwrap();
+        let config = LiveConfig::new(config, path, overrides);
+        config.cycle_profile().await;
+        assert_eq!(config.load().profile.as_deref(), Some("a"));
+        assert!(config.error().is_none());
+
+        config.cycle_profile().await;
+        assert_eq!(config.load().profile.as_deref(), Some("a"));
+        assert!(config.error().is_some());
+
+        // reloads after file changes keep using the working profile
+        assert!(config.reload().await);
+        assert_eq!(config.load().general.scroll_offset, 1);
+        assert!(config.error().is_none());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
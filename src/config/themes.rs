@@ -0,0 +1,150 @@
+use anyhow::{anyhow, Context};
+use crossterm::style::{Attribute, Stylize};
+use ratatui::widgets::BorderType;
+use toml::{value::Table, Value};
+
+use super::{TextModifier, ThemeConfiguration};
+
+/// Themes bundled with verses, with their description
+pub const PRESETS: [(&str, &str, &str); 5] = [
+    (
+        "catppuccin",
+        "Catppuccin Mocha pastels",
+        include_str!("themes/catppuccin.toml"),
+    ),
+    (
+        "gruvbox",
+        "Warm retro colors of Gruvbox dark",
+        include_str!("themes/gruvbox.toml"),
+    ),
+    (
+        "nord",
+        "Cold arctic blues of Nord",
+        include_str!("themes/nord.toml"),
+    ),
+    (
+        "solarized",
+        "Solarized dark",
+        include_str!("themes/solarized.toml"),
+    ),
+    (
+        "monochrome",
+        "Grays only, works in any terminal",
+        include_str!("themes/monochrome.toml"),
+    ),
+];
+
+/// Parses a bundled theme by its name
+pub fn preset(name: &str) -> anyhow::Result<ThemeConfiguration> {
+    Ok(preset_table(name)?.try_into()?)
+}
+
+pub fn preset_table(name: &str) -> anyhow::Result<Table> {
+    let (_, _, source) = PRESETS
+        .iter()
+        .find(|(preset, _, _)| *preset == name)
+        .ok_or_else(|| {
+            let names = PRESETS.map(|(name, _, _)| name).join(", ");
+            anyhow!("Unknown theme preset `{name}`, available presets: {names}")
+        })?;
+    toml::from_str(source).with_context(|| format!("Invalid bundled theme `{name}`"))
+}
+
+/// Replaces the `preset` key of the theme section with the preset's values.
+/// Other keys of the section override the preset
+pub fn apply_preset(theme: &mut Value) -> anyhow::Result<()> {
+    let table = match theme.as_table_mut() {
+        Some(table) => table,
+        None => return Ok(()),
+    };
+    let name = match table.remove("preset") {
+        Some(Value::String(name)) => name,
+        Some(_) => anyhow::bail!("`theme.preset` must be a string"),
+        None => return Ok(()),
+    };
+    let mut merged = preset_table(&name)?;
+    merge(&mut merged, std::mem::take(table));
+    *table = merged;
+    Ok(())
+}
+
+/// Recursively merges `overrides` into `base`, tables are merged key by key
+pub(super) fn merge(base: &mut Table, overrides: Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(value)) => merge(base, value),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Renders a small sample of the lyrics box and progress bar in the theme's colors
+pub fn preview(theme: &ThemeConfiguration) -> String {
+    const WIDTH: usize = 40;
+    let lines = [
+        ("And I said", &theme.lyrics.past_text_color),
+        ("Hey, what's going on?", &None),
+        ("Twenty-five years and my life is still", &None),
+    ];
+    let symbols = BorderType::line_symbols(theme.borders.lyrics_border_style.0);
+    let border = |it: &str| {
+        it.to_owned()
+            .with(theme.borders.lyrics_border_color.0.into())
+    };
+    let title = " Track name ";
+
+    let mut out = format!(
+        "{}{}{}{}\n",
+        border(symbols.top_left),
+        title.with(theme.borders.lyrics_border_text_color.0.into()),
+        border(&symbols.horizontal.repeat(WIDTH - title.len())),
+        border(symbols.top_right)
+    );
+    for (idx, (words, color)) in lines.iter().enumerate() {
+        let color = if idx == 1 {
+            &theme.lyrics.active_text_color
+        } else {
+            color.as_ref().unwrap_or(&theme.lyrics.inactive_text_color)
+        };
+        let modifiers = if idx == 1 {
+            &theme.lyrics.active_modifiers
+        } else {
+            &theme.lyrics.inactive_modifiers
+        };
+        let words = modifiers.iter().fold(
+            format!("{words:<WIDTH$}").with(color.0.into()),
+            |styled, it| styled.attribute(modifier_attribute(*it)),
+        );
+        out.push_str(&format!(
+            "{}{words}{}\n",
+            border(symbols.vertical),
+            border(symbols.vertical)
+        ));
+    }
+    out.push_str(&format!(
+        "{}{}{}\n",
+        border(symbols.bottom_left),
+        border(&symbols.horizontal.repeat(WIDTH)),
+        border(symbols.bottom_right)
+    ));
+    let filled = WIDTH * 2 / 5;
+    out.push_str(&format!(
+        " {}{} 01:23/03:45",
+        "█".repeat(filled).with(theme.progress_bar.color.0.into()),
+        "░"
+            .repeat(WIDTH - filled - 12)
+            .with(theme.progress_bar.color.0.into())
+    ));
+    out
+}
+
+fn modifier_attribute(modifier: TextModifier) -> Attribute {
+    match modifier {
+        TextModifier::Bold => Attribute::Bold,
+        TextModifier::Dim => Attribute::Dim,
+        TextModifier::Italic => Attribute::Italic,
+        TextModifier::Underlined => Attribute::Underlined,
+    }
+}
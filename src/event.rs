@@ -1,4 +1,6 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
+
+use image::RgbImage;
 
 use crate::verses::Lyrics;
 
@@ -17,6 +19,24 @@ pub enum StatusEvent {
     TrackProgress {
         new_progress_ms: u32,
     },
+    PlaybackState {
+        is_playing: bool,
+    },
+    /// Problem that stopped a part of verses, like a plugin, shown by the backend
+    Error {
+        message: String,
+    },
+    /// Album cover, loaded after the track of the album was sent
+    AlbumArt {
+        album_id: String,
+        image: Arc<RgbImage>,
+    },
+}
+
+/// Commands sent from the backend to control Spotify playback
+#[derive(Debug, Clone)]
+pub enum PlaybackCommand {
+    Seek { position_ms: u32 },
 }
 
 #[derive(Default, Debug, Clone)]
@@ -27,4 +47,10 @@ pub struct TrackMetadata {
     pub track_duration: Duration,
     pub artist_genres: Vec<String>,
     pub popularity: u32,
+    /// Additional info fields provided by plugins
+    pub extra_fields: Vec<(String, String)>,
+    pub album_id: Option<String>,
+    pub album_art_url: Option<String>,
+    /// Downscaled album cover, if it was loaded
+    pub album_art: Option<Arc<RgbImage>>,
 }
@@ -1,32 +1,37 @@
 pub mod config;
 pub mod event;
 mod oauth;
+pub mod paths;
+mod setup;
 pub mod verses;
 
 use std::{
-    io::{stdin, stdout, BufRead, Write},
+    io::{stdin, IsTerminal},
+    path::{Path, PathBuf},
     sync::Arc,
 };
 
-use clap::Parser;
-use config::VersesConfig;
+use clap::{Parser, Subcommand, ValueEnum};
+use config::{
+    diagnostics::{ConfigErrors, Diagnostic, Severity},
+    migrate::migrate,
+    overrides::Overrides,
+    reload::{self, LiveConfig},
+    themes, VersesConfig,
+};
 
+use paths::Paths;
 use rspotify::{prelude::*, scopes, AuthCodePkceSpotify, Config, Credentials, OAuth};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use verses::Verses;
+use verses::{
+    format::template_registry,
+    plugins::PluginHost,
+    tmux_backend::{StatusSnapshot, TmuxBackend, DEFAULT_STATUS_FORMAT},
+    tui_backend::TerminalUiBackend,
+    Verses,
+};
 
 use crate::oauth::server_oneshot;
 
-async fn prepare_dirs() -> anyhow::Result<()> {
-    let cache_dir = home::home_dir().unwrap().join(".cache").join("verses");
-    let config_dir = home::home_dir().unwrap().join(".config").join("verses");
-    tokio::fs::create_dir_all(config_dir).await?;
-    tokio::fs::create_dir_all(cache_dir).await?;
-    Ok(())
-}
-
-const EXAMPLE_CONFIG: &str = include_str!("./config.example.toml");
-
 /// A TUI spotify synchronized lyrics viewer
 #[derive(Parser)]
 #[command(about, author, version, long_about = None)]
@@ -34,76 +39,307 @@ struct Args {
     /// Whether to just validate the config and exit
     #[arg(long, short)]
     validate: bool,
+    /// Output format of `--validate`
+    #[arg(long, value_enum, default_value = "text")]
+    format: OutputFormat,
+    /// Config file to use instead of `config.toml` in the config directory
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Directory of the config file, its includes and plugins. Defaults to `$XDG_CONFIG_HOME/verses`
+    #[arg(long, global = true)]
+    config_dir: Option<PathBuf>,
+    /// Override a config key, e.g. `--set general.scroll_offset=6`. Can be repeated
+    #[arg(long = "set", value_name = "KEY=VALUE", global = true)]
+    set: Vec<String>,
+    /// Profile of the config to use, defined in `[profiles.<name>]`
+    #[arg(long, global = true)]
+    profile: Option<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-async fn parse_config() -> anyhow::Result<VersesConfig> {
-    let config_dir = home::home_dir()
-        .unwrap()
-        .join(".config")
-        .join("verses")
-        .join("config.toml");
-    if !config_dir.exists() {
-        println!("Looks like it's your first time launching Verses!");
-        println!("To setup, enter your spotify app client id here.");
-        println!("You can create a new app here: https://developer.spotify.com/dashboard/create");
-        println!(
-            "[IMPORTANT] Make sure to set Redirect URI to \"http://localhost:8888/callback\"!"
-        );
-        print!("Enter client ID (not client secret!) here: ");
-        stdout().lock().flush()?;
+#[derive(ValueEnum, Clone, Copy)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Continuously write the current lyric line into a file for the tmux status line
+    Tmux {
+        /// File to write the status line into. Defaults to `$XDG_CACHE_HOME/verses/tmux_status`
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Handlebars format of the status line
+        #[arg(long, short, default_value = DEFAULT_STATUS_FORMAT)]
+        format: String,
+        /// Truncate the status line to this amount of columns
+        #[arg(long, short = 'w')]
+        max_width: Option<usize>,
+    },
+    /// Query the state of a running `verses tmux` instance
+    Ctl {
+        #[command(subcommand)]
+        query: CtlQuery,
+    },
+    /// Manage the config file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// List and preview the bundled themes
+    Themes {
+        #[command(subcommand)]
+        command: ThemesCommand,
+    },
+    /// Create the config interactively, asking for the Spotify client ID and preferences
+    Init,
+}
+
+#[derive(Subcommand, Clone)]
+enum ConfigCommand {
+    /// Add keys missing from the config and report the unknown ones
+    Migrate {
+        /// Only report the changes, without writing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
 
-        let mut stdin = stdin().lock();
-        let mut buf = String::new();
-        stdin.read_line(&mut buf)?;
-        let buf = &buf[..buf.len() - 1]; // exclude newline
+#[derive(Subcommand, Clone)]
+enum ThemesCommand {
+    /// Print the names of the bundled themes
+    List,
+    /// Print a sample of the theme's colors, all themes are shown if no name is given
+    Preview { name: Option<String> },
+}
 
-        println!("Saving default configuration...");
-        let new_config = EXAMPLE_CONFIG.replace("{{SPOTIFY_CLIENT_ID}}", buf);
+#[derive(Subcommand, Clone)]
+enum CtlQuery {
+    /// Print the current lyric line
+    CurrentLine {
+        /// Handlebars format of the printed line
+        #[arg(long, short, default_value = DEFAULT_STATUS_FORMAT)]
+        format: String,
+        /// Truncate the printed line to this amount of columns
+        #[arg(long, short = 'w')]
+        max_width: Option<usize>,
+    },
+}
 
-        let mut file = tokio::fs::File::create(&config_dir).await?;
-        file.write_all(new_config.as_bytes()).await?;
-        drop(file);
-        println!("Your config has been saved to {config_dir:?}!")
+async fn run_ctl(query: CtlQuery) -> anyhow::Result<()> {
+    match query {
+        CtlQuery::CurrentLine { format, max_width } => {
+            // Printing nothing if `verses tmux` is not running
+            if let Some(snapshot) = StatusSnapshot::read_latest().await? {
+                println!(
+                    "{}",
+                    snapshot.render(&template_registry(), &format, max_width)?
+                );
+            }
+        }
     }
+    Ok(())
+}
 
-    let mut file = tokio::fs::File::open(&config_dir).await?;
-    let mut buf = String::new();
-    file.read_to_string(&mut buf).await?;
+async fn run_config(command: ConfigCommand, path: &Path) -> anyhow::Result<()> {
+    match command {
+        ConfigCommand::Migrate { dry_run } => {
+            let source = tokio::fs::read_to_string(path).await?;
+            let (migrated, report) = migrate(&source)?;
+            if report.is_empty() {
+                println!("Config is up to date");
+                return Ok(());
+            }
+            for key in &report.added {
+                println!("+ {key} (added with the default value)");
+            }
+            for key in &report.required {
+                println!("! {key} is missing and has to be set manually");
+            }
+            for key in &report.unknown {
+                println!("? {key} is unknown or deprecated, it is not used by verses");
+            }
+            if dry_run || report.added.is_empty() {
+                return Ok(());
+            }
+            let backup = path.with_extension("toml.bak");
+            tokio::fs::copy(path, &backup).await?;
+            tokio::fs::write(path, migrated).await?;
+            println!("Config updated, the old version is saved to {backup:?}");
+        }
+    }
+    Ok(())
+}
 
-    VersesConfig::read_from_str(&buf).await
+fn run_themes(command: ThemesCommand) -> anyhow::Result<()> {
+    match command {
+        ThemesCommand::List => {
+            for (name, description, _) in themes::PRESETS {
+                println!("{name:<12} {description}");
+            }
+        }
+        ThemesCommand::Preview { name } => {
+            let names = match &name {
+                Some(name) => vec![name.as_str()],
+                None => themes::PRESETS.iter().map(|(name, _, _)| *name).collect(),
+            };
+            for name in names {
+                println!("{name}\n{}\n", themes::preview(&themes::preset(name)?));
+            }
+            println!("Use a theme with `theme = {{ preset = \"<name>\" }}` in your config");
+        }
+    }
+    Ok(())
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    // Create necessary directories first
-    prepare_dirs().await?;
+/// Reads the config, running the setup wizard if there is none yet.
+/// Returns `None` if the wizard was quit without writing a config
+async fn parse_config(
+    config_path: &Path,
+    overrides: &Overrides,
+    profile: Option<&str>,
+) -> anyhow::Result<Option<VersesConfig>> {
+    if !config_path.exists() {
+        if !stdin().is_terminal() {
+            anyhow::bail!(
+                "There is no config at {}, run `verses init` to create one",
+                config_path.display()
+            );
+        }
+        println!("Looks like it's your first time launching Verses!");
+        setup::run(config_path).await?;
+        if !config_path.exists() {
+            return Ok(None);
+        }
+    }
 
-    let args = Args::parse();
+    VersesConfig::read_from_file(config_path, overrides, profile)
+        .await
+        .map(Some)
+}
 
-    // Parsing config
-    let verses_config = Arc::new(parse_config().await?);
-    if args.validate {
-        println!("Config validated");
-        return Ok(());
+/// Errors of the profiles that are not in use, which would only show up when switching to them
+async fn profile_errors(
+    config: &VersesConfig,
+    config_path: &Path,
+    overrides: &Overrides,
+) -> anyhow::Result<Vec<Diagnostic>> {
+    let mut errors = Vec::new();
+    for name in &config.profiles {
+        if config.profile.as_ref() == Some(name) {
+            continue;
+        }
+        if let Err(err) = VersesConfig::read_from_file(config_path, overrides, Some(name)).await {
+            let profile_errors = err.downcast::<ConfigErrors>()?.0;
+            errors.extend(
+                profile_errors
+                    .into_iter()
+                    .filter(|it| it.severity == Severity::Error),
+            );
+        }
+    }
+    Ok(errors)
+}
+
+/// Checks the config and plugins, exiting with a non-zero code if there are errors
+async fn validate(
+    config_path: &Path,
+    overrides: &Overrides,
+    profile: Option<&str>,
+    format: OutputFormat,
+) -> anyhow::Result<()> {
+    let parsed = if config_path.exists() {
+        VersesConfig::read_from_file(config_path, overrides, profile).await
+    } else {
+        // the setup wizard is not started, since validating should not change anything
+        let diagnostic = Diagnostic::at_key(Severity::Error, config_path, "", &[])
+            .message("no config found, run `verses init` to create one");
+        Err(ConfigErrors(vec![diagnostic]).into())
+    };
+    let (config, mut diagnostics) = match parsed {
+        Ok(config) => {
+            let mut diagnostics = config.warnings.clone();
+            diagnostics.extend(profile_errors(&config, config_path, overrides).await?);
+            (Some(config), diagnostics)
+        }
+        Err(err) => match err.downcast::<ConfigErrors>() {
+            Ok(errors) => (None, errors.0),
+            Err(err) => return Err(err),
+        },
+    };
+    let mut plugin_names = Vec::new();
+    if let Some(config) = &config {
+        let plugins_dir = paths::get()?.plugins_dir();
+        let (plugins, plugin_errors) = PluginHost::load(&plugins_dir, &config.plugins);
+        plugin_names = plugins.names().map(str::to_owned).collect();
+        diagnostics.extend(plugin_errors.iter().map(|err| Diagnostic {
+            severity: Severity::Error,
+            file: plugins_dir.clone(),
+            line: None,
+            column: None,
+            key: None,
+            message: format!("{err:#}"),
+            expected: None,
+            suggestion: None,
+        }));
+    }
+    let valid = diagnostics.iter().all(|it| it.severity != Severity::Error);
+
+    match format {
+        OutputFormat::Text => {
+            for diagnostic in &diagnostics {
+                eprintln!("{diagnostic}");
+            }
+            for name in &plugin_names {
+                println!("Loaded plugin {name}");
+            }
+            if valid {
+                println!("Config validated");
+            }
+        }
+        OutputFormat::Json => {
+            let report = serde_json::json!({
+                "valid": valid,
+                "diagnostics": diagnostics,
+                "plugins": plugin_names,
+            });
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+    }
+    if !valid {
+        std::process::exit(1);
     }
+    Ok(())
+}
 
-    let creds = Credentials::new_pkce(&verses_config.api.spotify_client_id);
+/// Authenticates with Spotify, returning `None` if authentication was not completed
+async fn login(config: &VersesConfig) -> anyhow::Result<Option<AuthCodePkceSpotify>> {
+    if config.api.spotify_client_id.is_empty() {
+        anyhow::bail!(
+            "`api.spotify_client_id` is not set in the config, run `verses init` to set it"
+        );
+    }
+    let creds = Credentials::new_pkce(&config.api.spotify_client_id);
+    let mut scopes = scopes!("user-read-playback-state");
+    if config.playback.control {
+        // cached tokens without this scope are discarded, prompting to log in again
+        scopes.insert("user-modify-playback-state".to_owned());
+    }
     let oauth = OAuth {
-        redirect_uri: "http://localhost:8888/callback".to_string(),
-        scopes: scopes!("user-read-playback-state"),
+        redirect_uri: format!("http://localhost:{}/callback", config.api.redirect_port),
+        scopes,
         ..Default::default()
     };
 
-    let config = Config {
+    let spotify_config = Config {
         token_cached: true,
-        cache_path: home::home_dir()
-            .unwrap()
-            .join(".cache")
-            .join("verses")
-            .join("spotify.json"),
+        cache_path: paths::get()?.spotify_token(),
         ..Default::default()
     };
-    let mut spotify = AuthCodePkceSpotify::with_config(creds.clone(), oauth.clone(), config);
+    let mut spotify =
+        AuthCodePkceSpotify::with_config(creds.clone(), oauth.clone(), spotify_config);
     if let Ok(Some(tk)) = spotify.read_token_cache(true).await {
         *spotify.get_token().lock().await.unwrap() = Some(tk);
         spotify.refresh_token().await?;
@@ -117,20 +353,86 @@ async fn main() -> anyhow::Result<()> {
             }
             Err(err) => {
                 eprintln!("Failed to open a web browser! {err}");
-                return Ok(());
+                return Ok(None);
             }
         }
 
-        let (code, oauth_state) = server_oneshot().await?;
+        let (code, oauth_state) = server_oneshot(config.api.redirect_port).await?;
         if oauth.state != oauth_state {
             println!("Failed to login! Did you open an old auth URL?");
-            return Ok(());
+            return Ok(None);
         }
         spotify.request_token(&code).await?;
     }
 
-    let verses = Verses::new(spotify, verses_config);
-    verses.run().await?;
+    Ok(Some(spotify))
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    // Create necessary directories first
+    let paths = paths::init(Paths::resolve(args.config_dir.clone())?);
+    paths.create_dirs().await?;
+
+    let config_path = args.config.clone().unwrap_or_else(|| paths.config_file());
+    if let Some(Command::Ctl { query }) = &args.command {
+        return run_ctl(query.clone()).await;
+    }
+    if let Some(Command::Config { command }) = &args.command {
+        return run_config(command.clone(), &config_path).await;
+    }
+    if let Some(Command::Themes { command }) = &args.command {
+        return run_themes(command.clone());
+    }
+    if let Some(Command::Init) = &args.command {
+        return setup::run(&config_path).await;
+    }
+
+    // Parsing config
+    let overrides = Overrides::collect(&args.set)?;
+    if args.validate {
+        let profile = args.profile.as_deref();
+        return validate(&config_path, &overrides, profile, args.format).await;
+    }
+    let verses_config =
+        match parse_config(&config_path, &overrides, args.profile.as_deref()).await? {
+            Some(config) => config,
+            None => return Ok(()),
+        };
+    let plugins_dir = paths.plugins_dir();
+    let plugins_config = verses_config.plugins.clone();
+    let (plugins, plugin_errors) =
+        tokio::task::spawn_blocking(move || PluginHost::load(&plugins_dir, &plugins_config))
+            .await?;
+    for err in &plugin_errors {
+        eprintln!("{err:#}");
+    }
+
+    let spotify = if let Some(spotify) = login(&verses_config).await? {
+        spotify
+    } else {
+        return Ok(());
+    };
+
+    let verses_config = LiveConfig::new(verses_config, config_path, overrides);
+    if let Err(err) = reload::spawn_watcher(verses_config.clone()) {
+        eprintln!("Config changes will not be applied until restart: {err:#}");
+    }
+    let verses = Verses::new(spotify, verses_config, Arc::new(plugins));
+    match args.command {
+        Some(Command::Tmux {
+            output,
+            format,
+            max_width,
+        }) => {
+            let output = output.unwrap_or_else(|| paths.tmux_status());
+            verses
+                .run(TmuxBackend::new(output, format, max_width))
+                .await?
+        }
+        _ => verses.run(TerminalUiBackend::default()).await?,
+    }
 
     Ok(())
 }
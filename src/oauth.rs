@@ -4,8 +4,8 @@ use tokio::{
     net::{TcpListener, TcpStream},
 };
 
-pub async fn server_oneshot() -> anyhow::Result<(String, String)> {
-    let tcp_listener = TcpListener::bind("127.0.0.1:8888").await?;
+pub async fn server_oneshot(port: u16) -> anyhow::Result<(String, String)> {
+    let tcp_listener = TcpListener::bind(("127.0.0.1", port)).await?;
 
     // Only accept a single connection
     while let Ok((client, _)) = tcp_listener.accept().await {
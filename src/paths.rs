@@ -0,0 +1,113 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use anyhow::anyhow;
+
+static PATHS: OnceLock<Paths> = OnceLock::new();
+
+/// Directories verses keeps its files in, following the XDG base directory specification
+#[derive(Debug, Clone)]
+pub struct Paths {
+    /// Config file, its includes and plugins
+    pub config_dir: PathBuf,
+    /// Files that can be safely deleted, like downloaded album art
+    pub cache_dir: PathBuf,
+    /// Files that are kept between launches, like panel visibility
+    pub state_dir: PathBuf,
+}
+
+impl Paths {
+    /// Resolves the directories from the environment, `config_dir` replaces the XDG config directory
+    pub fn resolve(config_dir: Option<PathBuf>) -> anyhow::Result<Self> {
+        let config_dir = match config_dir {
+            Some(config_dir) => config_dir,
+            None => xdg_dir("XDG_CONFIG_HOME", &[".config"])?,
+        };
+        Ok(Self {
+            config_dir,
+            cache_dir: xdg_dir("XDG_CACHE_HOME", &[".cache"])?,
+            state_dir: xdg_dir("XDG_STATE_HOME", &[".local", "state"])?,
+        })
+    }
+
+    pub fn config_file(&self) -> PathBuf {
+        self.config_dir.join("config.toml")
+    }
+
+    pub fn plugins_dir(&self) -> PathBuf {
+        self.config_dir.join("plugins")
+    }
+
+    pub fn spotify_token(&self) -> PathBuf {
+        self.cache_dir.join("spotify.json")
+    }
+
+    pub fn album_art_dir(&self) -> PathBuf {
+        self.cache_dir.join("album_art")
+    }
+
+    pub fn tmux_status(&self) -> PathBuf {
+        self.cache_dir.join("tmux_status")
+    }
+
+    /// Playback state written by `verses tmux` for `verses ctl`
+    pub fn now_playing(&self) -> PathBuf {
+        self.cache_dir.join("now_playing.json")
+    }
+
+    pub fn ui_state(&self) -> PathBuf {
+        self.state_dir.join("ui_state.json")
+    }
+
+    pub async fn create_dirs(&self) -> anyhow::Result<()> {
+        for dir in [&self.config_dir, &self.cache_dir, &self.state_dir] {
+            tokio::fs::create_dir_all(dir)
+                .await
+                .map_err(|err| anyhow!("Failed to create {}: {err}", dir.display()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Sets the directories used by [`get`], has no effect if they were already resolved
+pub fn init(paths: Paths) -> &'static Paths {
+    PATHS.get_or_init(|| paths)
+}
+
+/// Directories set by [`init`], resolved from the environment if it was not called
+pub fn get() -> anyhow::Result<&'static Paths> {
+    match PATHS.get() {
+        Some(paths) => Ok(paths),
+        None => Ok(init(Paths::resolve(None)?)),
+    }
+}
+
+/// Writes into `<file name>.tmp` first, so readers never observe a partially written file
+pub async fn write_atomic(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_owned();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    tokio::fs::write(&tmp_path, contents).await?;
+    tokio::fs::rename(tmp_path, path).await?;
+    Ok(())
+}
+
+/// `$<var>/verses` if the variable is set to an absolute path, otherwise `~/<fallback>/verses`
+fn xdg_dir(var: &str, fallback: &[&str]) -> anyhow::Result<PathBuf> {
+    let base = match std::env::var_os(var).map(PathBuf::from) {
+        // relative paths are invalid according to the specification and should be ignored
+        Some(base) if base.is_absolute() => base,
+        _ => fallback.iter().fold(home_dir()?, |dir, it| dir.join(it)),
+    };
+    Ok(base.join("verses"))
+}
+
+fn home_dir() -> anyhow::Result<PathBuf> {
+    home::home_dir()
+        .filter(|it| it != Path::new(""))
+        .ok_or_else(|| {
+            anyhow!("Could not find the home directory, set $HOME or the XDG_* variables")
+        })
+}
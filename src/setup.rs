@@ -0,0 +1,355 @@
+use std::{
+    collections::HashSet,
+    io::{stdin, stdout, BufRead, Write},
+    path::Path,
+};
+
+use anyhow::bail;
+
+use crate::config::{overrides::Overrides, themes, VersesConfig, EXAMPLE_CONFIG};
+
+const DEFAULT_PORT: u16 = 8888;
+
+/// Answers given to the setup wizard
+struct Answers {
+    client_id: String,
+    preset: Option<&'static str>,
+    romanize_unicode: bool,
+    romanize_exclude: Vec<String>,
+    romanize_track_names: bool,
+    redirect_port: u16,
+}
+
+/// Asks for the client ID and a few preferences, and writes the config after confirming it
+pub async fn run(config_path: &Path) -> anyhow::Result<()> {
+    let mut prompt = Prompt::new();
+    if config_path.exists()
+        && !prompt.confirm(
+            &format!(
+                "A config already exists at {}. Overwrite it?",
+                config_path.display()
+            ),
+            false,
+        )?
+    {
+        println!("Keeping the existing config");
+        return Ok(());
+    }
+
+    println!("Welcome to Verses! Let's set up your config.");
+    println!();
+    println!("Verses needs a Spotify app to see what you are listening to.");
+    println!("You can create a new app here: https://developer.spotify.com/dashboard/create");
+    let client_id = prompt.ask_until("Client ID (not client secret!)", None, |it| {
+        validate_client_id(it).map(str::to_owned)
+    })?;
+
+    println!();
+    println!("Themes:");
+    println!("  {:<12} colors from the default config", "none");
+    for (name, description, _) in themes::PRESETS {
+        println!("  {name:<12} {description}");
+    }
+    let preset = prompt.ask_until("Theme", Some("none"), |it| match it {
+        "none" => Ok(None),
+        name => themes::PRESETS
+            .iter()
+            .find(|(preset, _, _)| *preset == name)
+            .map(|(preset, _, _)| Some(*preset))
+            .ok_or_else(|| format!("There is no `{name}` theme")),
+    })?;
+
+    println!();
+    let romanize_unicode = prompt.confirm(
+        "Romanize lyrics in non-latin scripts (japanese, korean, cyrillic, etc.)?",
+        true,
+    )?;
+    let (romanize_exclude, romanize_track_names) = if romanize_unicode {
+        let exclude = prompt.ask(
+            "Languages to keep as is, comma separated (e.g. `de, fr`)",
+            Some("de"),
+        )?;
+        let exclude = exclude
+            .split(',')
+            .map(|it| it.trim().to_lowercase())
+            .filter(|it| !it.is_empty())
+            .collect();
+        (exclude, prompt.confirm("Romanize track names too?", false)?)
+    } else {
+        (Vec::new(), false)
+    };
+
+    println!();
+    let redirect_port = prompt.ask_until(
+        "Port of the redirect URI",
+        Some(&DEFAULT_PORT.to_string()),
+        |it| match it.parse::<u16>() {
+            Ok(port) if port != 0 => Ok(port),
+            _ => Err("The port has to be a number from 1 to 65535".to_owned()),
+        },
+    )?;
+
+    let answers = Answers {
+        client_id,
+        preset,
+        romanize_unicode,
+        romanize_exclude,
+        romanize_track_names,
+        redirect_port,
+    };
+    let rendered = render_config(&answers);
+    // the example config is valid, so errors here are bugs of the wizard
+    let config =
+        VersesConfig::read_from_str(&rendered, config_path, &Overrides::default(), None).await?;
+
+    println!();
+    println!("Theme: {}", answers.preset.unwrap_or("none"));
+    println!("{}", themes::preview(&config.theme));
+    println!(
+        "Romanization: {}",
+        match (
+            answers.romanize_unicode,
+            answers.romanize_exclude.is_empty()
+        ) {
+            (false, _) => "disabled".to_owned(),
+            (true, true) => "enabled".to_owned(),
+            (true, false) => format!("enabled, except {}", answers.romanize_exclude.join(", ")),
+        }
+    );
+    println!(
+        "[IMPORTANT] Make sure to set the Redirect URI of your app to \"http://localhost:{}/callback\"!",
+        answers.redirect_port
+    );
+    println!();
+    if !prompt.confirm(
+        &format!("Save the config to {}?", config_path.display()),
+        true,
+    )? {
+        println!("Nothing was saved");
+        return Ok(());
+    }
+
+    if let Some(dir) = config_path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    if config_path.exists() {
+        let backup = config_path.with_extension("toml.bak");
+        tokio::fs::copy(config_path, &backup).await?;
+        println!("The old config is saved to {}", backup.display());
+    }
+    tokio::fs::write(config_path, rendered).await?;
+    println!("Your config has been saved to {}!", config_path.display());
+    Ok(())
+}
+
+/// Client IDs are 32 hexadecimal characters
+fn validate_client_id(id: &str) -> Result<&str, String> {
+    if id.len() == 32 && id.chars().all(|it| it.is_ascii_hexdigit()) {
+        Ok(id)
+    } else {
+        Err(
+            "A client ID has 32 characters, each either a digit or a letter from `a` to `f` in any case"
+                .to_owned(),
+        )
+    }
+}
+
+/// Fills the answers into the example config, keeping its comments.
+/// With a theme preset, the example's theme keys it sets are commented out, since they would override it
+fn render_config(answers: &Answers) -> String {
+    let preset_keys = answers.preset.map(preset_keys).unwrap_or_default();
+    let exclude = toml::Value::Array(
+        answers
+            .romanize_exclude
+            .iter()
+            .map(|it| toml::Value::String(it.clone()))
+            .collect(),
+    );
+    let replacements = [
+        (
+            "general",
+            "romanize_unicode",
+            answers.romanize_unicode.to_string(),
+        ),
+        ("general", "romanize_exclude", exclude.to_string()),
+        (
+            "general",
+            "romanize_track_names",
+            answers.romanize_track_names.to_string(),
+        ),
+        ("api", "redirect_port", answers.redirect_port.to_string()),
+    ];
+
+    let mut out = String::new();
+    let mut section = String::new();
+    for line in EXAMPLE_CONFIG.lines() {
+        let mut line = line.replace("{{SPOTIFY_CLIENT_ID}}", &answers.client_id);
+        if let Some(preset) = answers.preset {
+            // the preset is documented in a commented out `[theme]` section
+            if line == "# [theme]" {
+                line = "[theme]".to_owned();
+            } else if section == "theme" && line.starts_with("# preset = ") {
+                line = format!("preset = {}", toml::Value::from(preset));
+            }
+        }
+        if let Some(header) = line.strip_prefix('[').and_then(|it| it.strip_suffix(']')) {
+            section = header.to_owned();
+        } else if let Some((key, _)) = line.split_once(" = ") {
+            let replacement = replacements
+                .iter()
+                .find(|(it_section, it_key, _)| *it_section == section && *it_key == key);
+            if let Some((_, _, value)) = replacement {
+                line = format!("{key} = {value}");
+            } else if preset_keys.contains(&format!("{section}.{key}")) {
+                line = format!("# {line}");
+            }
+        }
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+/// Dotted paths of the keys set by the preset, relative to the config
+fn preset_keys(name: &str) -> HashSet<String> {
+    fn collect(table: &toml::Table, prefix: &str, keys: &mut HashSet<String>) {
+        for (key, value) in table {
+            let path = format!("{prefix}.{key}");
+            match value.as_table() {
+                Some(table) => collect(table, &path, keys),
+                None => {
+                    keys.insert(path);
+                }
+            }
+        }
+    }
+    let mut keys = HashSet::new();
+    if let Ok(table) = themes::preset_table(name) {
+        collect(&table, "theme", &mut keys);
+    }
+    keys
+}
+
+/// Questions on the terminal, answered one line at a time
+struct Prompt {
+    input: std::io::StdinLock<'static>,
+}
+
+impl Prompt {
+    fn new() -> Self {
+        Self {
+            input: stdin().lock(),
+        }
+    }
+
+    /// Reads an answer, empty answers are replaced with the default
+    fn ask(&mut self, question: &str, default: Option<&str>) -> anyhow::Result<String> {
+        match default {
+            Some(default) => print!("{question} [{default}]: "),
+            None => print!("{question}: "),
+        }
+        stdout().lock().flush()?;
+
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            println!();
+            bail!("Setup was cancelled, no config was written");
+        }
+        let answer = line.trim();
+        Ok(match default {
+            Some(default) if answer.is_empty() => default.to_owned(),
+            _ => answer.to_owned(),
+        })
+    }
+
+    /// Asks again until the answer is accepted by `parse`
+    fn ask_until<T>(
+        &mut self,
+        question: &str,
+        default: Option<&str>,
+        parse: impl Fn(&str) -> Result<T, String>,
+    ) -> anyhow::Result<T> {
+        loop {
+            match parse(&self.ask(question, default)?) {
+                Ok(value) => return Ok(value),
+                Err(err) => println!("{err}"),
+            }
+        }
+    }
+
+    fn confirm(&mut self, question: &str, default: bool) -> anyhow::Result<bool> {
+        let default_answer = if default { "Y/n" } else { "y/N" };
+        self.ask_until(question, Some(default_answer), |it| {
+            match it.to_lowercase().as_str() {
+                "y" | "yes" => Ok(true),
+                "n" | "no" => Ok(false),
+                _ if it == default_answer => Ok(default),
+                _ => Err("Please answer `y` or `n`".to_owned()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENT_ID: &str = "0123456789ABCDEF0123456789abcdef";
+
+    fn answers(preset: Option<&'static str>) -> Answers {
+        Answers {
+            client_id: CLIENT_ID.to_owned(),
+            preset,
+            romanize_unicode: true,
+            romanize_exclude: vec!["fr".to_owned(), "it".to_owned()],
+            romanize_track_names: true,
+            redirect_port: 9000,
+        }
+    }
+
+    async fn read(source: &str) -> VersesConfig {
+        VersesConfig::read_from_str(
+            source,
+            Path::new("config.toml"),
+            &Overrides::default(),
+            None,
+        )
+        .await
+        .unwrap()
+    }
+
+    #[test]
+    fn accepts_client_ids_in_any_case() {
+        assert!(validate_client_id(CLIENT_ID).is_ok());
+        assert!(validate_client_id("0123456789abcdef").is_err());
+        assert!(validate_client_id("0123456789abcdef0123456789abcdeg").is_err());
+    }
+
+    #[tokio::test]
+    async fn renders_the_answers() {
+        let config = read(&render_config(&answers(None))).await;
+        assert!(config.warnings.is_empty(), "{:?}", config.warnings);
+        assert_eq!(config.api.spotify_client_id, CLIENT_ID);
+        assert_eq!(config.api.redirect_port, 9000);
+        assert!(config.general.romanize_unicode);
+        assert_eq!(config.general.romanize_exclude, ["fr", "it"]);
+        assert!(config.general.romanize_track_names);
+    }
+
+    #[tokio::test]
+    async fn renders_every_preset() {
+        for (name, _, _) in themes::PRESETS {
+            let config = read(&render_config(&answers(Some(name)))).await;
+            assert!(config.warnings.is_empty(), "{name}: {:?}", config.warnings);
+            assert_eq!(config.api.spotify_client_id, CLIENT_ID);
+
+            // keys of the example config do not override the preset
+            let preset_only = read(&format!("[theme]\npreset = \"{name}\"\n")).await;
+            assert_eq!(
+                format!("{:?}", config.theme),
+                format!("{:?}", preset_only.theme),
+                "{name}"
+            );
+        }
+    }
+}
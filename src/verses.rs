@@ -1,65 +1,128 @@
+pub mod album_art;
+pub mod format;
 pub mod handler;
+pub mod hooks;
+pub mod instrumental;
+pub mod karaoke;
+pub mod line_style;
+pub mod notifier;
+pub mod palette;
+pub mod plugins;
+pub mod romanize;
+pub mod search;
+pub mod tmux_backend;
 pub mod tui_backend;
+pub mod ui_state;
 
 use std::{sync::Arc, time::Duration};
 
 use reqwest::{Client, StatusCode};
 use rspotify::{
-    model::{CurrentlyPlayingContext, FullArtist, FullTrack, TrackId},
+    model::{CurrentlyPlayingContext, FullArtist, FullTrack, Image, TrackId},
     prelude::*,
     AuthCodePkceSpotify,
 };
 use serde::Deserialize;
 
 use crate::{
-    config::VersesConfig,
-    event::{StatusEvent, TrackMetadata},
-    verses::handler::VersesHandler,
+    config::{reload::SharedConfig, AlbumArtConfiguration},
+    event::{PlaybackCommand, StatusEvent, TrackMetadata},
+    verses::handler::{VersesBackend, VersesHandler},
 };
 
-use self::tui_backend::TerminalUiBackend;
+use self::{
+    album_art::fetch_album_art,
+    hooks::HookRunner,
+    notifier::DesktopNotifier,
+    plugins::{PluginEventListener, PluginHost},
+};
+
+/// Album covers that take longer to load are not displayed
+const ALBUM_ART_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[derive(Debug, Clone)]
 pub struct Verses {
     spotify: AuthCodePkceSpotify,
     client: reqwest::Client,
-    config: Arc<VersesConfig>,
+    config: SharedConfig,
+    plugins: Arc<PluginHost>,
 }
 
 impl Verses {
-    pub fn new(spotify: AuthCodePkceSpotify, config: Arc<VersesConfig>) -> Self {
+    pub fn new(
+        spotify: AuthCodePkceSpotify,
+        config: SharedConfig,
+        plugins: Arc<PluginHost>,
+    ) -> Self {
         let client = Client::new();
         Self {
             spotify,
             client,
             config,
+            plugins,
         }
     }
 
-    pub async fn run(self) -> anyhow::Result<()> {
+    pub async fn run<B: VersesBackend + Send + Sync + 'static>(
+        self,
+        backend: B,
+    ) -> anyhow::Result<()> {
         let (events_tx, events_rx) = flume::bounded::<StatusEvent>(4);
+        let (commands_tx, commands_rx) = flume::bounded::<PlaybackCommand>(4);
 
-        // TODO: other backend choices?
-        let tui = VersesHandler::new(TerminalUiBackend::default());
+        let mut handler = VersesHandler::new(backend);
+        // listeners are only added on startup, enabling them requires a restart
+        let config = self.config.load();
+        if config.notifications.enabled {
+            handler.add_listener(DesktopNotifier::default());
+        }
+        if config.hooks.any_enabled() {
+            handler.add_listener(HookRunner::default());
+        }
+        if let Some(listener) = PluginEventListener::new(self.plugins.clone()) {
+            handler.add_listener(listener);
+        }
 
-        let cfg_clone_backend: Arc<VersesConfig> = self.config.clone();
-        tokio::task::spawn(async move { self.run_dispatcher(events_tx).await });
-        tui.run(events_rx, cfg_clone_backend).await?;
+        let cfg_clone_backend: SharedConfig = self.config.clone();
+        tokio::task::spawn(async move { self.run_dispatcher(events_tx, commands_rx).await });
+        handler
+            .run(events_rx, commands_tx, cfg_clone_backend)
+            .await?;
 
         Ok(())
     }
 
-    async fn run_dispatcher(&self, events_tx: flume::Sender<StatusEvent>) -> anyhow::Result<()> {
+    async fn run_dispatcher(
+        &self,
+        events_tx: flume::Sender<StatusEvent>,
+        commands_rx: flume::Receiver<PlaybackCommand>,
+    ) -> anyhow::Result<()> {
         let mut cached_id: String = String::new();
         let mut cached_lyrics: Option<Lyrics> = None;
         let mut current_lyrics_line: isize = 0;
+        let mut is_playing: Option<bool> = None;
 
         // Fetching status every second
         while let Ok(status) = self.currently_playing().await {
+            for message in self.plugins.take_errors() {
+                events_tx.send_async(StatusEvent::Error { message }).await?;
+            }
+            let now_playing = status.as_ref().map(|it| it.is_playing).unwrap_or(false);
+            if is_playing != Some(now_playing) {
+                events_tx
+                    .send_async(StatusEvent::PlaybackState {
+                        is_playing: now_playing,
+                    })
+                    .await?;
+                is_playing = Some(now_playing);
+            }
+
             if let Some(status) = status {
                 let item = if let Some(item) = status.item {
                     item
                 } else {
+                    // Probably an ad or a local file, retry later
+                    self.wait_for_update(&commands_rx).await;
                     continue;
                 };
                 let id = item.id().unwrap();
@@ -100,7 +163,7 @@ impl Verses {
                             current_lyrics_line = lyrics_line_index;
                         }
                     }
-                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    self.wait_for_update(&commands_rx).await;
                     continue;
                 } else {
                     cached_id = id.clone();
@@ -113,11 +176,27 @@ impl Verses {
                     .await?;
 
                 let track = self.spotify.track(TrackId::from_id(&id)?, None).await?;
-                let main_artist = track.artists.get(0).unwrap();
+                let main_artist = track.artists.first().unwrap();
                 let main_artist = self.spotify.artist(main_artist.id.clone().unwrap()).await?;
-                let metadata = extract_track_meta(track, main_artist);
+                let mut metadata = extract_track_meta(track, main_artist);
+
+                let config = self.config.load();
+                let album_art = config
+                    .album_art
+                    .enabled
+                    .then(|| (metadata.clone(), config.album_art.clone()));
 
                 let lyrics = self.fetch_lyrics(&id).await?;
+                let plugins = self.plugins.clone();
+                let (metadata, lyrics) = tokio::task::spawn_blocking(move || {
+                    metadata.extra_fields = plugins.info_fields(&metadata, lyrics.as_ref());
+                    let lyrics = lyrics.map(|mut lyrics| {
+                        plugins.transform_lyrics(&mut lyrics);
+                        lyrics
+                    });
+                    (metadata, lyrics)
+                })
+                .await?;
                 if let Some(new_lyrics) = lyrics {
                     cached_lyrics = Some(new_lyrics.clone());
                     events_tx
@@ -131,18 +210,77 @@ impl Verses {
                         .send_async(StatusEvent::NewTrackNoLyrics { metadata })
                         .await?;
                 }
+                // sent after the track, so the cover is not dropped as one of a previous track
+                if let Some((metadata, cfg)) = album_art {
+                    self.spawn_album_art(metadata, cfg, events_tx.clone());
+                }
             } else {
                 // Not playing anything, retry later
+                self.wait_for_update(&commands_rx).await;
                 continue;
             }
         }
         Ok(())
     }
 
+    /// Loads the album cover in background, so a slow image server does not delay the lyrics
+    fn spawn_album_art(
+        &self,
+        metadata: TrackMetadata,
+        cfg: AlbumArtConfiguration,
+        events_tx: flume::Sender<StatusEvent>,
+    ) {
+        let client = self.client.clone();
+        tokio::task::spawn(async move {
+            let album_id = metadata.album_id.clone()?;
+            // tracks are still displayed if the cover could not be loaded
+            let image =
+                tokio::time::timeout(ALBUM_ART_TIMEOUT, fetch_album_art(&client, &metadata, &cfg))
+                    .await
+                    .ok()?
+                    .ok()??;
+            let _ = events_tx
+                .send_async(StatusEvent::AlbumArt {
+                    album_id,
+                    image: Arc::new(image),
+                })
+                .await;
+            Some(())
+        });
+    }
+
+    /// Waits until the next status update, or until a playback command
+    /// is received, so its effect is displayed right away
+    async fn wait_for_update(&self, commands_rx: &flume::Receiver<PlaybackCommand>) {
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+            Ok(command) = commands_rx.recv_async() => {
+                if self.config.load().playback.control {
+                    // playback control may be unavailable, e.g. without Spotify Premium
+                    let _ = self.run_command(command).await;
+                }
+            }
+        }
+    }
+
+    async fn run_command(&self, command: PlaybackCommand) -> anyhow::Result<()> {
+        match command {
+            PlaybackCommand::Seek { position_ms } => {
+                self.spotify
+                    .seek_track(chrono::Duration::milliseconds(position_ms as i64), None)
+                    .await?
+            }
+        }
+        Ok(())
+    }
+
     async fn fetch_lyrics(&self, track_id: &str) -> anyhow::Result<Option<Lyrics>> {
         let resp = self
             .client
-            .get(format!("{}{track_id}", self.config.api.lyricstify_api_url))
+            .get(format!(
+                "{}{track_id}",
+                self.config.load().api.lyricstify_api_url
+            ))
             .send()
             .await?;
         if resp.status() == StatusCode::NOT_FOUND {
@@ -173,9 +311,23 @@ fn extract_track_meta(track: FullTrack, artist: FullArtist) -> TrackMetadata {
         track_duration: track.duration.to_std().unwrap(),
         artist_genres: artist.genres,
         popularity: track.popularity,
+        extra_fields: Vec::new(),
+        album_id: track.album.id.map(|it| it.id().to_owned()),
+        album_art_url: album_art_url(&track.album.images),
+        album_art: None,
     }
 }
 
+/// Picks the smallest image that is still large enough to look good in the terminal
+fn album_art_url(images: &[Image]) -> Option<String> {
+    images
+        .iter()
+        .filter(|it| it.width.unwrap_or(0) >= 300)
+        .min_by_key(|it| it.width)
+        .or_else(|| images.first())
+        .map(|it| it.url.clone())
+}
+
 #[derive(Debug, Clone, Deserialize)]
 struct LyricsObject {
     lyrics: Lyrics,
@@ -0,0 +1,331 @@
+use std::{io::Cursor, path::Path};
+
+use base64::Engine;
+use image::{imageops::FilterType, ImageOutputFormat, RgbImage};
+use ratatui::{
+    style::{Color, Style},
+    text::{Line, Span},
+};
+
+use crate::{
+    config::{AlbumArtConfiguration, GraphicsProtocol},
+    event::TrackMetadata,
+    paths::{self, write_atomic},
+};
+
+use super::format::template_registry;
+
+/// Album art is downscaled to this size after loading,
+/// which is more than enough for any terminal pane
+const MAX_ART_SIZE: u32 = 256;
+
+/// Cell size in pixels, used if the terminal does not report it
+const FALLBACK_CELL_SIZE: (u16, u16) = (8, 16);
+
+/// Loads the album cover of the track, using the disk cache if possible
+pub async fn fetch_album_art(
+    client: &reqwest::Client,
+    metadata: &TrackMetadata,
+    cfg: &AlbumArtConfiguration,
+) -> anyhow::Result<Option<RgbImage>> {
+    fetch_into(&paths::get()?.album_art_dir(), client, metadata, cfg).await
+}
+
+async fn fetch_into(
+    cache_dir: &Path,
+    client: &reqwest::Client,
+    metadata: &TrackMetadata,
+    cfg: &AlbumArtConfiguration,
+) -> anyhow::Result<Option<RgbImage>> {
+    let (url, album_id) = match (&metadata.album_art_url, &metadata.album_id) {
+        (Some(url), Some(album_id)) => (url, album_id),
+        _ => return Ok(None),
+    };
+
+    let cache_path = cache_dir.join(format!("{album_id}.img"));
+    if let Ok(bytes) = tokio::fs::read(&cache_path).await {
+        match decode(bytes).await {
+            Ok(image) => return Ok(Some(image)),
+            // the cached cover is broken, it is downloaded again
+            Err(_) => {
+                let _ = tokio::fs::remove_file(&cache_path).await;
+            }
+        }
+    }
+
+    let url = template_registry().render_template(
+        &cfg.url_format,
+        &serde_json::json!({ "url": url, "album_id": album_id }),
+    )?;
+    let bytes = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?
+        .to_vec();
+    // only covers that can be decoded are cached
+    let image = decode(bytes.clone()).await?;
+    tokio::fs::create_dir_all(cache_dir).await?;
+    write_atomic(&cache_path, &bytes).await?;
+    Ok(Some(image))
+}
+
+/// Decodes and downscales the cover. Decoding is slow enough to keep it off the async runtime
+async fn decode(bytes: Vec<u8>) -> anyhow::Result<RgbImage> {
+    let image = tokio::task::spawn_blocking(move || {
+        image::load_from_memory(&bytes).map(|it| {
+            it.resize(MAX_ART_SIZE, MAX_ART_SIZE, FilterType::Triangle)
+                .to_rgb8()
+        })
+    })
+    .await??;
+    Ok(image)
+}
+
+impl GraphicsProtocol {
+    /// Resolves `Auto` into the protocol supported by the terminal, based on the environment
+    pub fn detect(self) -> Self {
+        if self != GraphicsProtocol::Auto {
+            return self;
+        }
+        let var = |name: &str| std::env::var(name).unwrap_or_default();
+        let term = var("TERM");
+        let term_program = var("TERM_PROGRAM");
+        if std::env::var_os("KITTY_WINDOW_ID").is_some()
+            || term == "xterm-kitty"
+            || term_program == "WezTerm"
+            || term_program == "ghostty"
+        {
+            GraphicsProtocol::Kitty
+        } else if term.contains("sixel")
+            || term == "foot"
+            || term == "mlterm"
+            || term_program == "iTerm.app"
+        {
+            GraphicsProtocol::Sixel
+        } else {
+            GraphicsProtocol::HalfBlocks
+        }
+    }
+}
+
+/// Renders the image with `▀` characters, each cell displaying two pixels.
+/// The image is kept square and centered in the area
+pub fn half_blocks(image: &RgbImage, width: u16, height: u16) -> Vec<Line<'static>> {
+    // cells are about twice as tall as wide, so two pixels make a square
+    let size = (width as u32).min(height as u32 * 2);
+    if size == 0 {
+        return Vec::new();
+    }
+    let resized = image::imageops::resize(image, size, size, FilterType::Triangle);
+    let padding = " ".repeat((width as u32 - size) as usize / 2);
+
+    (0..size)
+        .step_by(2)
+        .map(|y| {
+            let mut spans = vec![Span::raw(padding.clone())];
+            spans.extend((0..size).map(|x| {
+                let top = resized.get_pixel(x, y).0;
+                let mut style = Style::default().fg(Color::Rgb(top[0], top[1], top[2]));
+                if y + 1 < size {
+                    let bottom = resized.get_pixel(x, y + 1).0;
+                    style = style.bg(Color::Rgb(bottom[0], bottom[1], bottom[2]));
+                }
+                Span::styled("▀", style)
+            }));
+            Line::from(spans)
+        })
+        .collect()
+}
+
+/// Escape sequence displaying the image at the cursor, using the kitty graphics protocol.
+/// The terminal scales the image to the provided amount of cells
+pub fn kitty_sequence(image: &RgbImage, columns: u16, rows: u16) -> anyhow::Result<String> {
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)?;
+    let encoded = base64::engine::general_purpose::STANDARD.encode(png);
+
+    // previous images are deleted first
+    let mut out = String::from("\x1b_Ga=d,q=2\x1b\\");
+    // payload has to be sent in chunks of at most 4096 bytes
+    let chunks = encoded.as_bytes().chunks(4096).collect::<Vec<_>>();
+    for (idx, chunk) in chunks.iter().enumerate() {
+        let more = (idx + 1 < chunks.len()) as u8;
+        let chunk = std::str::from_utf8(chunk)?;
+        if idx == 0 {
+            out.push_str(&format!(
+                "\x1b_Ga=T,f=100,q=2,c={columns},r={rows},m={more};{chunk}\x1b\\"
+            ));
+        } else {
+            out.push_str(&format!("\x1b_Gm={more};{chunk}\x1b\\"));
+        }
+    }
+    Ok(out)
+}
+
+/// Escape sequence displaying the image at the cursor, using sixel graphics.
+/// Colors are reduced to a 6x6x6 color cube
+pub fn sixel_sequence(image: &RgbImage, columns: u16, rows: u16) -> String {
+    let (cell_width, cell_height) = cell_size();
+    // keeping the image square
+    let size = (columns as u32 * cell_width as u32).min(rows as u32 * cell_height as u32);
+    if size == 0 {
+        return String::new();
+    }
+    let resized = image::imageops::resize(image, size, size, FilterType::Triangle);
+    let level = |it: u8| (it as u32 * 5 + 127) / 255;
+    let color_of = |x: u32, y: u32| {
+        let [r, g, b] = resized.get_pixel(x, y).0;
+        (level(r) * 36 + level(g) * 6 + level(b)) as usize
+    };
+
+    let mut out = String::from("\x1bPq");
+    out.push_str(&format!("\"1;1;{size};{size}"));
+    for idx in 0..216 {
+        let percent = |it: usize| it * 100 / 5;
+        out.push_str(&format!(
+            "#{idx};2;{};{};{}",
+            percent(idx / 36),
+            percent((idx / 6) % 6),
+            percent(idx % 6)
+        ));
+    }
+
+    // each sixel row encodes six rows of pixels
+    for band in (0..size).step_by(6) {
+        let mut used = [false; 216];
+        for y in band..(band + 6).min(size) {
+            for x in 0..size {
+                used[color_of(x, y)] = true;
+            }
+        }
+        for color in (0..216).filter(|it| used[*it]) {
+            out.push_str(&format!("#{color}"));
+            for x in 0..size {
+                let mut bits = 0u8;
+                for dy in 0..6 {
+                    let y = band + dy;
+                    if y < size && color_of(x, y) == color {
+                        bits |= 1 << dy;
+                    }
+                }
+                out.push((63 + bits) as char);
+            }
+            // carriage return, to overlay the next color
+            out.push('$');
+        }
+        // next band
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+fn cell_size() -> (u16, u16) {
+    match crossterm::terminal::window_size() {
+        Ok(size) if size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0 => {
+            (size.width / size.columns, size.height / size.rows)
+        }
+        _ => FALLBACK_CELL_SIZE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use super::*;
+
+    /// Serves the same body to every request, counting them
+    fn serve(body: Vec<u8>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/cover.png", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+                counter.fetch_add(1, Ordering::SeqCst);
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                let _ = stream.write_all(head.as_bytes());
+                let _ = stream.write_all(&body);
+            }
+        });
+        (url, requests)
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = RgbImage::from_pixel(width, height, image::Rgb([255, 0, 0]));
+        let mut png = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+            .unwrap();
+        png
+    }
+
+    #[tokio::test]
+    async fn caches_covers_and_refetches_broken_ones() {
+        let (url, requests) = serve(png(512, 512));
+        let cache_dir: PathBuf =
+            std::env::temp_dir().join(format!("verses-album-art-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&cache_dir);
+        let cache_path = cache_dir.join("album.img");
+
+        let client = reqwest::Client::new();
+        let metadata = TrackMetadata {
+            album_art_url: Some(url),
+            album_id: Some("album".to_owned()),
+            ..Default::default()
+        };
+        let cfg = AlbumArtConfiguration::default();
+        let fetch = || fetch_into(&cache_dir, &client, &metadata, &cfg);
+
+        let image = fetch().await.unwrap().unwrap();
+        assert_eq!(image.dimensions(), (MAX_ART_SIZE, MAX_ART_SIZE));
+        assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0]);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert!(image::load_from_memory(&std::fs::read(&cache_path).unwrap()).is_ok());
+        assert!(!cache_dir.join("album.img.tmp").exists());
+
+        // served from the disk cache
+        fetch().await.unwrap().unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        std::fs::write(&cache_path, b"not an image").unwrap();
+        let image = fetch().await.unwrap().unwrap();
+        assert_eq!(image.dimensions(), (MAX_ART_SIZE, MAX_ART_SIZE));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert!(image::load_from_memory(&std::fs::read(&cache_path).unwrap()).is_ok());
+
+        let _ = std::fs::remove_dir_all(&cache_dir);
+    }
+
+    #[tokio::test]
+    async fn skips_tracks_without_cover() {
+        let client = reqwest::Client::new();
+        let metadata = TrackMetadata::default();
+        let cfg = AlbumArtConfiguration::default();
+        let image = fetch_into(Path::new("unused"), &client, &metadata, &cfg).await;
+        assert!(image.unwrap().is_none());
+    }
+}
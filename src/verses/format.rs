@@ -0,0 +1,163 @@
+use handlebars::{handlebars_helper, Handlebars};
+use ratatui::{
+    style::Style,
+    text::{Line, Span},
+};
+use unicode_width::UnicodeWidthChar;
+
+/// Creates a new Handlebars registry with all verses helpers registered
+pub fn template_registry() -> Handlebars<'static> {
+    let mut reg = Handlebars::new();
+    // we are rendering to a terminal, not to HTML
+    reg.register_escape_fn(handlebars::no_escape);
+    handlebars_helper!(join_helper: |input: Vec<String>| { input.join(", ") });
+    reg.register_helper("join", Box::new(join_helper));
+    reg
+}
+
+#[inline]
+pub fn fmt_duration(duration_ms: u32) -> String {
+    let mut seconds = duration_ms / 1000;
+    let minutes = seconds / 60;
+    seconds %= 60;
+    format!("{minutes:0>2}:{seconds:0>2}")
+}
+
+/// Truncates the string to fit into `max_width` terminal columns,
+/// appending an ellipsis if anything was cut off
+pub fn truncate_width(str: &str, max_width: usize) -> String {
+    let mut width = 0;
+    let mut out = String::with_capacity(str.len());
+    for char in str.chars() {
+        let char_width = char.width().unwrap_or(0);
+        if width + char_width > max_width {
+            // making space for the ellipsis
+            while width + 1 > max_width {
+                match out.pop() {
+                    Some(popped) => width -= popped.width().unwrap_or(0),
+                    None => return out,
+                }
+            }
+            out.push('…');
+            return out;
+        }
+        width += char_width;
+        out.push(char);
+    }
+    out
+}
+
+/// Word-wraps a line into rows of at most `max_width` terminal columns,
+/// breaking words that do not fit into a single row
+pub fn wrap_line(line: &Line, max_width: usize) -> Vec<Line<'static>> {
+    let chars = line
+        .spans
+        .iter()
+        .flat_map(|span| span.content.chars().map(move |char| (char, span.style)))
+        .collect::<Vec<_>>();
+    if max_width == 0 {
+        return vec![styled_row(&chars, line)];
+    }
+
+    let mut rows = vec![Vec::new()];
+    let mut row_width = 0;
+    let mut idx = 0;
+    while idx < chars.len() {
+        // splitting into words and runs of whitespace
+        let is_space = chars[idx].0.is_whitespace();
+        let end = chars[idx..]
+            .iter()
+            .position(|(char, _)| char.is_whitespace() != is_space)
+            .map(|it| idx + it)
+            .unwrap_or(chars.len());
+        let word = &chars[idx..end];
+        idx = end;
+
+        if is_space {
+            // whitespace is dropped at the start of wrapped rows
+            if row_width == 0 && rows.len() > 1 {
+                continue;
+            }
+            for (char, style) in word {
+                let char_width = char.width().unwrap_or(0);
+                if row_width + char_width > max_width {
+                    break;
+                }
+                rows.last_mut().unwrap().push((*char, *style));
+                row_width += char_width;
+            }
+            continue;
+        }
+
+        let word_width = word
+            .iter()
+            .map(|(char, _)| char.width().unwrap_or(0))
+            .sum::<usize>();
+        if row_width > 0 && row_width + word_width > max_width {
+            new_row(&mut rows);
+            row_width = 0;
+        }
+        for (char, style) in word {
+            let char_width = char.width().unwrap_or(0);
+            if row_width > 0 && row_width + char_width > max_width {
+                new_row(&mut rows);
+                row_width = 0;
+            }
+            rows.last_mut().unwrap().push((*char, *style));
+            row_width += char_width;
+        }
+    }
+
+    rows.iter().map(|row| styled_row(row, line)).collect()
+}
+
+/// Starts a new row, trimming whitespace at the end of the previous one
+fn new_row(rows: &mut Vec<Vec<(char, Style)>>) {
+    if let Some(row) = rows.last_mut() {
+        while row.last().map(|(char, _)| char.is_whitespace()) == Some(true) {
+            row.pop();
+        }
+    }
+    rows.push(Vec::new());
+}
+
+/// Joins characters back into spans of the same style
+fn styled_row(chars: &[(char, Style)], line: &Line) -> Line<'static> {
+    let mut spans: Vec<Span<'static>> = Vec::new();
+    for (char, style) in chars {
+        match spans.last_mut() {
+            Some(span) if span.style == *style => span.content.to_mut().push(*char),
+            _ => spans.push(Span::styled(char.to_string(), *style)),
+        }
+    }
+    Line {
+        spans,
+        alignment: line.alignment,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_short_strings() {
+        assert_eq!(truncate_width("lyrics", 6), "lyrics");
+        assert_eq!(truncate_width("", 0), "");
+    }
+
+    #[test]
+    fn truncates_with_ellipsis() {
+        assert_eq!(truncate_width("lyrics line", 6), "lyric…");
+        assert_eq!(truncate_width("lyrics", 1), "…");
+        assert_eq!(truncate_width("lyrics", 0), "");
+    }
+
+    #[test]
+    fn counts_wide_characters() {
+        // each of these takes two columns
+        assert_eq!(truncate_width("歌詞です", 8), "歌詞です");
+        assert_eq!(truncate_width("歌詞です", 7), "歌詞で…");
+        assert_eq!(truncate_width("歌詞です", 6), "歌詞…");
+    }
+}
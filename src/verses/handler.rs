@@ -5,8 +5,8 @@ use std::{
 };
 
 use crate::{
-    config::VersesConfig,
-    event::{StatusEvent, TrackMetadata},
+    config::reload::SharedConfig,
+    event::{PlaybackCommand, StatusEvent, TrackMetadata},
 };
 
 use super::Lyrics;
@@ -19,6 +19,9 @@ pub struct LyricsTracker {
     pub current_line: isize,
     pub current_progress_ms: u32,
     pub track_data: TrackMetadata,
+    pub is_playing: bool,
+    /// Errors of listeners and plugins that stopped, shown by the backend
+    pub errors: Vec<String>,
 }
 
 impl LyricsTracker {
@@ -35,6 +38,7 @@ impl LyricsTracker {
         self.track_data.track_album.hash(&mut hasher);
         self.track_data.track_artists.hash(&mut hasher);
         self.track_data.track_name.hash(&mut hasher);
+        self.track_data.extra_fields.hash(&mut hasher);
 
         hasher.finish()
     }
@@ -43,6 +47,7 @@ impl LyricsTracker {
 pub struct VersesHandler<T: VersesBackend> {
     tracker: SyncTracker,
     backend: T,
+    listeners: Vec<Box<dyn VersesListener + Send>>,
 }
 
 #[async_trait::async_trait]
@@ -50,35 +55,83 @@ pub trait VersesBackend {
     async fn run_backend(
         &mut self,
         tracker: SyncTracker,
-        config: Arc<VersesConfig>,
+        config: SharedConfig,
+        commands: flume::Sender<PlaybackCommand>,
     ) -> anyhow::Result<()>;
 }
 
+/// Listeners receive every status event alongside the backend.
+/// Events are delivered without blocking, so a slow listener
+/// may miss some of them, but never stalls the lyrics display
+#[async_trait::async_trait]
+pub trait VersesListener {
+    async fn run_listener(
+        &mut self,
+        events: flume::Receiver<StatusEvent>,
+        config: SharedConfig,
+    ) -> anyhow::Result<()>;
+}
+
+/// Amount of events buffered for each listener
+const LISTENER_BUFFER_SIZE: usize = 16;
+
 impl<T: VersesBackend + Send + Sync + 'static> VersesHandler<T> {
     pub fn new(backend: T) -> Self {
         Self {
             tracker: Arc::new(Mutex::new(Default::default())),
             backend,
+            listeners: Vec::new(),
         }
     }
 
+    pub fn add_listener<L: VersesListener + Send + 'static>(&mut self, listener: L) {
+        self.listeners.push(Box::new(listener));
+    }
+
     pub async fn run(
         mut self,
         event_rx: flume::Receiver<StatusEvent>,
-        config: Arc<VersesConfig>,
+        commands_tx: flume::Sender<PlaybackCommand>,
+        config: SharedConfig,
     ) -> anyhow::Result<()> {
+        let listener_txs = self
+            .listeners
+            .drain(..)
+            .map(|mut listener| {
+                let (tx, rx) = flume::bounded::<StatusEvent>(LISTENER_BUFFER_SIZE);
+                let config = config.clone();
+                let tracker = self.tracker.clone();
+                tokio::task::spawn(async move {
+                    if let Err(err) = listener.run_listener(rx, config).await {
+                        let mut tracker = tracker.lock().unwrap();
+                        tracker.errors.push(format!("{err:#}"));
+                    }
+                });
+                tx
+            })
+            .collect::<Vec<_>>();
+
         let tracker_w = self.tracker.clone();
         let tracker_r = self.tracker;
-        tokio::task::spawn(async move { Self::run_event_handler(tracker_w, event_rx).await });
-        self.backend.run_backend(tracker_r, config).await?;
+        tokio::task::spawn(async move {
+            Self::run_event_handler(tracker_w, event_rx, listener_txs).await
+        });
+        self.backend
+            .run_backend(tracker_r, config, commands_tx)
+            .await?;
         Ok(())
     }
 
     async fn run_event_handler(
         tracker: Arc<Mutex<LyricsTracker>>,
         event_rx: flume::Receiver<StatusEvent>,
+        listener_txs: Vec<flume::Sender<StatusEvent>>,
     ) -> anyhow::Result<()> {
         while let Ok(event) = event_rx.recv_async().await {
+            for tx in &listener_txs {
+                // full or closed listeners are skipped
+                let _ = tx.try_send(event.clone());
+            }
             match event {
                 StatusEvent::NewTrack {
                     metadata,
@@ -106,6 +159,20 @@ impl<T: VersesBackend + Send + Sync + 'static> VersesHandler<T> {
                     let mut tracker = tracker.lock().unwrap();
                     tracker.current_progress_ms = new_progress_ms;
                 }
+                StatusEvent::PlaybackState { is_playing } => {
+                    let mut tracker = tracker.lock().unwrap();
+                    tracker.is_playing = is_playing;
+                }
+                StatusEvent::Error { message } => {
+                    tracker.lock().unwrap().errors.push(message);
+                }
+                StatusEvent::AlbumArt { album_id, image } => {
+                    let mut tracker = tracker.lock().unwrap();
+                    // the track may have changed while the cover was loading
+                    if tracker.track_data.album_id.as_ref() == Some(&album_id) {
+                        tracker.track_data.album_art = Some(image);
+                    }
+                }
             }
         }
         Ok(())
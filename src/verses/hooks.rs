@@ -0,0 +1,178 @@
+use std::{
+    collections::HashMap,
+    process::Stdio,
+    time::{Duration, Instant},
+};
+
+use tokio::process::Command;
+
+use crate::{
+    config::{reload::SharedConfig, VersesConfig},
+    event::{StatusEvent, TrackMetadata},
+};
+
+use super::{handler::VersesListener, Lyrics};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum HookKind {
+    TrackChange,
+    LineChange,
+    NoLyrics,
+    Pause,
+}
+
+impl HookKind {
+    fn name(self) -> &'static str {
+        match self {
+            HookKind::TrackChange => "track_change",
+            HookKind::LineChange => "line_change",
+            HookKind::NoLyrics => "no_lyrics",
+            HookKind::Pause => "pause",
+        }
+    }
+}
+
+/// Executes configured shell hooks on playback events,
+/// exporting track metadata as `VERSES_*` environment variables
+#[derive(Debug, Clone, Default)]
+pub struct HookRunner {
+    metadata: TrackMetadata,
+    lyrics: Lyrics,
+    has_lyrics: bool,
+    current_line: isize,
+    progress_ms: u32,
+    was_playing: Option<bool>,
+    last_runs: HashMap<HookKind, Instant>,
+}
+
+#[async_trait::async_trait]
+impl VersesListener for HookRunner {
+    async fn run_listener(
+        &mut self,
+        events: flume::Receiver<StatusEvent>,
+        config: SharedConfig,
+    ) -> anyhow::Result<()> {
+        while let Ok(event) = events.recv_async().await {
+            let config = config.load();
+            let hooks = &config.hooks;
+            match event {
+                StatusEvent::NewTrack {
+                    metadata,
+                    new_lyrics,
+                } => {
+                    self.metadata = metadata;
+                    self.lyrics = new_lyrics;
+                    self.has_lyrics = true;
+                    self.current_line = -1;
+                    self.run_hook(HookKind::TrackChange, &hooks.on_track_change, &config);
+                }
+                StatusEvent::NewTrackNoLyrics { metadata } => {
+                    self.metadata = metadata;
+                    self.lyrics = Lyrics::default();
+                    self.has_lyrics = false;
+                    self.current_line = -1;
+                    self.run_hook(HookKind::TrackChange, &hooks.on_track_change, &config);
+                    self.run_hook(HookKind::NoLyrics, &hooks.on_no_lyrics, &config);
+                }
+                StatusEvent::SwitchLyricLine { new_line } => {
+                    if new_line == -1 {
+                        continue;
+                    }
+                    self.current_line = new_line;
+                    self.run_hook(HookKind::LineChange, &hooks.on_line_change, &config);
+                }
+                StatusEvent::TrackProgress { new_progress_ms } => {
+                    self.progress_ms = new_progress_ms;
+                }
+                StatusEvent::PlaybackState { is_playing } => {
+                    if self.was_playing == Some(true) && !is_playing {
+                        self.run_hook(HookKind::Pause, &hooks.on_pause, &config);
+                    }
+                    self.was_playing = Some(is_playing);
+                }
+                StatusEvent::AlbumArt { .. } | StatusEvent::Error { .. } => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl HookRunner {
+    fn run_hook(&mut self, kind: HookKind, command: &Option<String>, config: &VersesConfig) {
+        let command = if let Some(command) = command {
+            command
+        } else {
+            return;
+        };
+
+        // rate limiting each hook separately
+        let min_interval = Duration::from_millis(config.hooks.min_interval_ms);
+        if let Some(last_run) = self.last_runs.get(&kind) {
+            if last_run.elapsed() < min_interval {
+                return;
+            }
+        }
+        self.last_runs.insert(kind, Instant::now());
+
+        let mut shell = if cfg!(windows) {
+            let mut cmd = Command::new("cmd");
+            cmd.arg("/C");
+            cmd
+        } else {
+            let mut cmd = Command::new("sh");
+            cmd.arg("-c");
+            cmd
+        };
+        let child = shell
+            .arg(command)
+            .envs(self.environment(kind))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+
+        if let Ok(mut child) = child {
+            // reaping the process in background, hooks never block the event handler
+            tokio::task::spawn(async move { child.wait().await });
+        }
+    }
+
+    fn environment(&self, kind: HookKind) -> Vec<(&'static str, String)> {
+        let line = if self.current_line >= 0 {
+            self.lyrics
+                .lines
+                .get(self.current_line as usize)
+                .map(|it| it.words.clone())
+                .unwrap_or_default()
+        } else {
+            String::new()
+        };
+
+        vec![
+            ("VERSES_EVENT", kind.name().to_owned()),
+            ("VERSES_TRACK_NAME", self.metadata.track_name.clone()),
+            (
+                "VERSES_TRACK_ARTISTS",
+                self.metadata.track_artists.join(", "),
+            ),
+            ("VERSES_TRACK_ALBUM", self.metadata.track_album.clone()),
+            (
+                "VERSES_TRACK_GENRES",
+                self.metadata.artist_genres.join(", "),
+            ),
+            (
+                "VERSES_TRACK_POPULARITY",
+                self.metadata.popularity.to_string(),
+            ),
+            (
+                "VERSES_TRACK_DURATION_MS",
+                self.metadata.track_duration.as_millis().to_string(),
+            ),
+            ("VERSES_HAS_LYRICS", self.has_lyrics.to_string()),
+            ("VERSES_LYRICS_LANGUAGE", self.lyrics.language.clone()),
+            ("VERSES_LINE", line),
+            ("VERSES_LINE_INDEX", self.current_line.to_string()),
+            ("VERSES_PROGRESS_MS", self.progress_ms.to_string()),
+        ]
+    }
+}
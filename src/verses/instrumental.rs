@@ -0,0 +1,81 @@
+use crate::config::InstrumentalConfiguration;
+
+use super::{format::fmt_duration, handler::LyricsTracker, LyricSyncType};
+
+/// Animation frames of the instrumental indicator
+const FRAMES: [&str; 4] = ["♪    ", "♪ ♫  ", "♪ ♫ ♪", "  ♫ ♪"];
+
+/// A part of the track without singing, until the next line starts
+#[derive(Debug, Clone, Copy)]
+pub struct InstrumentalBreak {
+    pub start_ms: u32,
+    pub end_ms: u32,
+}
+
+impl InstrumentalBreak {
+    /// Finds the break at the current track progress.
+    /// A break is either a line without words, or a long gap between two lines,
+    /// in which case it starts after half of the minimal gap, so the line can be sung
+    pub fn detect(tracker: &LyricsTracker, cfg: &InstrumentalConfiguration) -> Option<Self> {
+        if !cfg.enabled || tracker.lyrics.sync_type != LyricSyncType::LineSynced {
+            return None;
+        }
+        let lines = &tracker.lyrics.lines;
+        let progress_ms = tracker.current_progress_ms;
+
+        // lines without words are always shown as breaks
+        let (brk, forced) = if tracker.current_line < 0 {
+            // intro, before the first line
+            let first = lines.first()?;
+            let brk = Self {
+                start_ms: 0,
+                end_ms: first.start_time_ms,
+            };
+            (brk, false)
+        } else {
+            let current_line = tracker.current_line as usize;
+            let current = lines.get(current_line)?;
+            let next = lines.get(current_line + 1)?;
+            let gap_ms = next.start_time_ms.saturating_sub(current.start_time_ms);
+            let (start_ms, forced) = if is_instrumental(&current.words) {
+                (current.start_time_ms, true)
+            } else if gap_ms >= cfg.min_gap_ms {
+                (current.start_time_ms + cfg.min_gap_ms / 2, false)
+            } else {
+                return None;
+            };
+            let brk = Self {
+                start_ms,
+                end_ms: next.start_time_ms,
+            };
+            (brk, forced)
+        };
+
+        let long_enough = brk.end_ms.saturating_sub(brk.start_ms) >= cfg.min_gap_ms / 2;
+        if (forced || long_enough) && (brk.start_ms..brk.end_ms).contains(&progress_ms) {
+            Some(brk)
+        } else {
+            None
+        }
+    }
+
+    /// Renders the indicator, e.g. `♪ ♫ ♪  ●●●●○○○○○○  00:07`
+    pub fn indicator(&self, progress_ms: u32, dots: usize, frame: usize) -> String {
+        let elapsed = progress_ms.saturating_sub(self.start_ms) as usize;
+        let total = self.end_ms.saturating_sub(self.start_ms).max(1) as usize;
+        let filled = (elapsed * dots / total).min(dots);
+        format!(
+            "{}  {}{}  {}",
+            FRAMES[frame % FRAMES.len()],
+            "●".repeat(filled),
+            "○".repeat(dots - filled),
+            fmt_duration(self.end_ms.saturating_sub(progress_ms))
+        )
+    }
+}
+
+/// Whether the line only marks an instrumental part
+pub fn is_instrumental(words: &str) -> bool {
+    let words = words.trim();
+    words.is_empty() || words.chars().all(|it| it == '♪' || it.is_whitespace())
+}
@@ -0,0 +1,171 @@
+use font8x8::{UnicodeFonts, BASIC_FONTS, GREEK_FONTS, HIRAGANA_FONTS, LATIN_FONTS};
+
+use crate::config::KaraokeScale;
+
+/// Size of a single glyph in pixels
+const GLYPH_SIZE: usize = 8;
+
+/// Quadrant characters, indexed by the top left, top right,
+/// bottom left and bottom right pixel bits
+const QUADRANTS: [char; 16] = [
+    ' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█',
+];
+
+impl KaraokeScale {
+    /// Terminal columns and rows taken by a single glyph
+    fn glyph_cells(self) -> (usize, usize) {
+        match self {
+            // two columns per pixel, since terminal cells are about twice as tall as wide
+            KaraokeScale::Large => (GLYPH_SIZE * 2, GLYPH_SIZE),
+            KaraokeScale::Medium | KaraokeScale::Auto => (GLYPH_SIZE, GLYPH_SIZE / 2),
+            KaraokeScale::Small => (GLYPH_SIZE / 2, GLYPH_SIZE / 2),
+        }
+    }
+}
+
+/// Renders the text in large block characters, word-wrapped to fit into the area.
+/// The `Auto` scale picks the largest scale that fits.
+/// Returns `None` if the text does not fit even at the smallest scale
+pub fn big_text(
+    text: &str,
+    max_width: usize,
+    max_height: usize,
+    scale: KaraokeScale,
+) -> Option<Vec<String>> {
+    let scales: &[KaraokeScale] = match scale {
+        KaraokeScale::Auto => &[
+            KaraokeScale::Large,
+            KaraokeScale::Medium,
+            KaraokeScale::Small,
+        ],
+        _ => std::slice::from_ref(&scale),
+    };
+    let glyphs = glyphs(text);
+    scales
+        .iter()
+        .find_map(|scale| render_glyphs(&glyphs, max_width, max_height, *scale))
+}
+
+fn render_glyphs(
+    glyphs: &[[u8; 8]],
+    max_width: usize,
+    max_height: usize,
+    scale: KaraokeScale,
+) -> Option<Vec<String>> {
+    let (glyph_cols, glyph_rows) = scale.glyph_cells();
+    let max_glyphs = max_width / glyph_cols;
+    if max_glyphs == 0 {
+        return None;
+    }
+
+    let rows = wrap_glyphs(glyphs, max_glyphs);
+    // keeping an empty row between the wrapped rows
+    let height = rows.len() * (glyph_rows + 1) - 1;
+    if height > max_height {
+        return None;
+    }
+
+    let mut lines = Vec::with_capacity(height);
+    for (idx, row) in rows.iter().enumerate() {
+        if idx != 0 {
+            lines.push(String::new());
+        }
+        for y in 0..glyph_rows {
+            // rows are not trimmed, so they stay aligned when centered
+            lines.push(
+                row.iter()
+                    .flat_map(|glyph| (0..glyph_cols).map(move |x| cell(glyph, x, y, scale)))
+                    .collect::<String>(),
+            );
+        }
+    }
+    Some(lines)
+}
+
+/// Word-wraps glyphs into rows of at most `max_glyphs`,
+/// breaking words that do not fit into a single row
+fn wrap_glyphs(glyphs: &[[u8; 8]], max_glyphs: usize) -> Vec<Vec<[u8; 8]>> {
+    let mut rows: Vec<Vec<[u8; 8]>> = vec![Vec::new()];
+    for word in glyphs.split(is_blank).filter(|it| !it.is_empty()) {
+        let row = rows.last_mut().unwrap();
+        // one glyph is taken by the space between words
+        if !row.is_empty() && row.len() + 1 + word.len() <= max_glyphs {
+            row.push([0; 8]);
+            row.extend_from_slice(word);
+            continue;
+        }
+        if !row.is_empty() {
+            rows.push(Vec::new());
+        }
+        for chunk in word.chunks(max_glyphs) {
+            let row = rows.last_mut().unwrap();
+            if !row.is_empty() {
+                rows.push(Vec::new());
+            }
+            rows.last_mut().unwrap().extend_from_slice(chunk);
+        }
+    }
+    rows
+}
+
+fn cell(glyph: &[u8; 8], x: usize, y: usize, scale: KaraokeScale) -> char {
+    let pixel = |x: usize, y: usize| (glyph[y] >> x) & 1 == 1;
+    match scale {
+        KaraokeScale::Large => {
+            if pixel(x / 2, y) {
+                '█'
+            } else {
+                ' '
+            }
+        }
+        KaraokeScale::Medium | KaraokeScale::Auto => match (pixel(x, y * 2), pixel(x, y * 2 + 1)) {
+            (true, true) => '█',
+            (true, false) => '▀',
+            (false, true) => '▄',
+            (false, false) => ' ',
+        },
+        KaraokeScale::Small => {
+            let bits = [
+                pixel(x * 2, y * 2),
+                pixel(x * 2 + 1, y * 2),
+                pixel(x * 2, y * 2 + 1),
+                pixel(x * 2 + 1, y * 2 + 1),
+            ];
+            let idx = bits
+                .iter()
+                .enumerate()
+                .fold(0, |acc, (bit, set)| acc | ((*set as usize) << bit));
+            QUADRANTS[idx]
+        }
+    }
+}
+
+fn is_blank(glyph: &[u8; 8]) -> bool {
+    glyph.iter().all(|it| *it == 0)
+}
+
+/// Looks up glyphs for each character, transliterating
+/// characters missing from the font
+fn glyphs(text: &str) -> Vec<[u8; 8]> {
+    let mut glyphs = Vec::with_capacity(text.len());
+    for char in text.chars() {
+        if let Some(glyph) = glyph(char) {
+            glyphs.push(glyph);
+            continue;
+        }
+        let transliterated = deunicode::deunicode_char(char).unwrap_or("?");
+        glyphs.extend(transliterated.chars().map(|it| glyph(it).unwrap_or([0; 8])));
+    }
+    glyphs
+}
+
+fn glyph(char: char) -> Option<[u8; 8]> {
+    if char.is_whitespace() {
+        return Some([0; 8]);
+    }
+    BASIC_FONTS
+        .get(char)
+        .or_else(|| LATIN_FONTS.get(char))
+        .or_else(|| GREEK_FONTS.get(char))
+        .or_else(|| HIRAGANA_FONTS.get(char))
+}
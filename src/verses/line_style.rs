@@ -0,0 +1,195 @@
+use std::time::Duration;
+
+use ratatui::style::{Color, Modifier, Style};
+
+use crate::config::{TextModifier, ThemeLyrics};
+
+/// Whether the terminal advertises 24-bit color support
+pub fn supports_truecolor() -> bool {
+    matches!(
+        std::env::var("COLORTERM").as_deref(),
+        Ok("truecolor") | Ok("24bit")
+    )
+}
+
+/// Computes styles of lyric lines, depending on their distance from the active line
+pub struct LineStyler<'a> {
+    pub theme: &'a ThemeLyrics,
+    pub truecolor: bool,
+    /// Whether lyrics are synced, unsynced lyrics have no active line
+    pub synced: bool,
+    pub current_line: isize,
+    /// Line that was active before the current one
+    pub previous_line: isize,
+    /// Time since the active line changed
+    pub since_change: Duration,
+}
+
+impl LineStyler<'_> {
+    pub fn style(&self, idx: usize) -> Style {
+        let idx = idx as isize;
+        let color = self.color(idx);
+        let modifiers = if self.synced && idx == self.current_line {
+            &self.theme.active_modifiers
+        } else {
+            &self.theme.inactive_modifiers
+        };
+        modifiers
+            .iter()
+            .fold(Style::default().fg(color), |style, it| {
+                style.add_modifier(Modifier::from(*it))
+            })
+    }
+
+    /// Whether the active line is still being animated
+    pub fn in_transition(&self) -> bool {
+        self.truecolor && self.progress() < 1.0
+    }
+
+    fn color(&self, idx: isize) -> Color {
+        let color = if !self.synced {
+            self.theme.inactive_text_color.0
+        } else if self.in_transition() && idx == self.current_line {
+            lerp_color(
+                self.resting_color(idx),
+                self.theme.active_text_color.0,
+                self.progress(),
+            )
+        } else if self.in_transition() && idx == self.previous_line {
+            lerp_color(
+                self.theme.active_text_color.0,
+                self.resting_color(idx),
+                self.progress(),
+            )
+        } else if idx == self.current_line {
+            self.theme.active_text_color.0
+        } else {
+            self.resting_color(idx)
+        };
+
+        if !self.synced || self.theme.fade_distance == 0 {
+            return color;
+        }
+        let distance = (idx - self.current_line).unsigned_abs() as f32;
+        let factor = (distance / self.theme.fade_distance as f32).min(1.0);
+        if self.truecolor {
+            lerp_color(color, self.theme.fade_color.0, factor)
+        } else if factor >= 0.5 {
+            // no gradient without truecolor, switching to the faded color halfway instead
+            self.theme.fade_color.0
+        } else {
+            color
+        }
+    }
+
+    /// Color of an inactive line
+    fn resting_color(&self, idx: isize) -> Color {
+        let color = if idx < self.current_line {
+            &self.theme.past_text_color
+        } else {
+            &self.theme.upcoming_text_color
+        };
+        color.as_ref().unwrap_or(&self.theme.inactive_text_color).0
+    }
+
+    fn progress(&self) -> f32 {
+        if self.theme.transition_ms == 0 {
+            return 1.0;
+        }
+        (self.since_change.as_millis() as f32 / self.theme.transition_ms as f32).min(1.0)
+    }
+}
+
+impl From<TextModifier> for Modifier {
+    fn from(value: TextModifier) -> Self {
+        match value {
+            TextModifier::Bold => Modifier::BOLD,
+            TextModifier::Dim => Modifier::DIM,
+            TextModifier::Italic => Modifier::ITALIC,
+            TextModifier::Underlined => Modifier::UNDERLINED,
+        }
+    }
+}
+
+/// Linearly interpolates between two colors. Colors that
+/// have no RGB representation are switched halfway instead
+fn lerp_color(from: Color, to: Color, factor: f32) -> Color {
+    // keeping the original colors at the ends, since the terminal palette may differ
+    if factor <= 0.0 {
+        return from;
+    } else if factor >= 1.0 {
+        return to;
+    }
+    match (to_rgb(from), to_rgb(to)) {
+        (Some((r1, g1, b1)), Some((r2, g2, b2))) => {
+            let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * factor).round() as u8;
+            Color::Rgb(lerp(r1, r2), lerp(g1, g2), lerp(b1, b2))
+        }
+        _ if factor < 0.5 => from,
+        _ => to,
+    }
+}
+
+/// Named colors, in the order of their indices
+const BASIC_COLORS: [Color; 16] = [
+    Color::Black,
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::Gray,
+    Color::DarkGray,
+    Color::LightRed,
+    Color::LightGreen,
+    Color::LightYellow,
+    Color::LightBlue,
+    Color::LightMagenta,
+    Color::LightCyan,
+    Color::White,
+];
+
+/// RGB values of a color, named colors use the xterm palette
+pub fn to_rgb(color: Color) -> Option<(u8, u8, u8)> {
+    let rgb = match color {
+        Color::Rgb(r, g, b) => (r, g, b),
+        Color::Black => (0, 0, 0),
+        Color::Red => (205, 0, 0),
+        Color::Green => (0, 205, 0),
+        Color::Yellow => (205, 205, 0),
+        Color::Blue => (0, 0, 238),
+        Color::Magenta => (205, 0, 205),
+        Color::Cyan => (0, 205, 205),
+        Color::Gray => (229, 229, 229),
+        Color::DarkGray => (127, 127, 127),
+        Color::LightRed => (255, 0, 0),
+        Color::LightGreen => (0, 255, 0),
+        Color::LightYellow => (255, 255, 0),
+        Color::LightBlue => (92, 92, 255),
+        Color::LightMagenta => (255, 0, 255),
+        Color::LightCyan => (0, 255, 255),
+        Color::White => (255, 255, 255),
+        Color::Indexed(idx) => indexed_to_rgb(idx)?,
+        Color::Reset => return None,
+    };
+    Some(rgb)
+}
+
+fn indexed_to_rgb(idx: u8) -> Option<(u8, u8, u8)> {
+    match idx {
+        // the basic 16 colors
+        0..=15 => to_rgb(BASIC_COLORS[idx as usize]),
+        // 6x6x6 color cube
+        16..=231 => {
+            let idx = idx - 16;
+            let level = |it: u8| if it == 0 { 0 } else { 55 + it * 40 };
+            Some((level(idx / 36), level((idx / 6) % 6), level(idx % 6)))
+        }
+        // grayscale ramp
+        232..=255 => {
+            let level = 8 + (idx - 232) * 10;
+            Some((level, level, level))
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+
+use zbus::{connection::Builder, zvariant::Value, Connection};
+
+use crate::{
+    config::{reload::SharedConfig, VersesConfig},
+    event::{StatusEvent, TrackMetadata},
+};
+
+use super::{
+    format::{fmt_duration, template_registry},
+    handler::VersesListener,
+};
+
+const NOTIFICATIONS_DESTINATION: &str = "org.freedesktop.Notifications";
+const NOTIFICATIONS_PATH: &str = "/org/freedesktop/Notifications";
+
+/// Notifications are disabled after this many failures in a row
+const MAX_FAILURES: u32 = 3;
+
+/// Sends a freedesktop notification whenever a new track starts playing
+#[derive(Debug, Clone, Default)]
+pub struct DesktopNotifier {
+    /// ID of the previous notification, so it gets replaced instead of stacking up
+    last_notification_id: u32,
+}
+
+#[async_trait::async_trait]
+impl VersesListener for DesktopNotifier {
+    async fn run_listener(
+        &mut self,
+        events: flume::Receiver<StatusEvent>,
+        config: SharedConfig,
+    ) -> anyhow::Result<()> {
+        let connection = connect(config.load().notifications.bus_address.as_deref())
+            .await
+            .context("Desktop notifications are disabled, could not connect to D-Bus")?;
+
+        let mut failures = 0;
+        while let Ok(event) = events.recv_async().await {
+            let (metadata, has_lyrics) = match event {
+                StatusEvent::NewTrack { metadata, .. } => (metadata, true),
+                StatusEvent::NewTrackNoLyrics { metadata } => (metadata, false),
+                _ => continue,
+            };
+            // a single failure should not stop further notifications
+            match self
+                .notify(&connection, &config.load(), &metadata, has_lyrics)
+                .await
+            {
+                Ok(()) => failures = 0,
+                Err(err) => {
+                    failures += 1;
+                    if failures >= MAX_FAILURES {
+                        return Err(err.context(format!(
+                            "Desktop notifications are disabled after {failures} failures in a row"
+                        )));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn connect(bus_address: Option<&str>) -> zbus::Result<Connection> {
+    match bus_address {
+        Some(address) => Builder::address(address)?.build().await,
+        None => Connection::session().await,
+    }
+}
+
+impl DesktopNotifier {
+    async fn notify(
+        &mut self,
+        connection: &Connection,
+        config: &VersesConfig,
+        metadata: &TrackMetadata,
+        has_lyrics: bool,
+    ) -> anyhow::Result<()> {
+        let reg = template_registry();
+        let data = serde_json::json!({
+            "name": metadata.track_name,
+            "artists": metadata.track_artists,
+            "album": metadata.track_album,
+            "genres": metadata.artist_genres,
+            "popularity": metadata.popularity,
+            "duration": fmt_duration(metadata.track_duration.as_millis() as u32),
+            "has_lyrics": has_lyrics,
+        });
+        let summary = reg.render_template(&config.notifications.summary_format, &data)?;
+        let body = reg.render_template(&config.notifications.body_format, &data)?;
+
+        let reply = connection
+            .call_method(
+                Some(NOTIFICATIONS_DESTINATION),
+                NOTIFICATIONS_PATH,
+                Some(NOTIFICATIONS_DESTINATION),
+                "Notify",
+                &(
+                    "verses",
+                    self.last_notification_id,
+                    "",
+                    summary,
+                    body,
+                    Vec::<&str>::new(),
+                    HashMap::<&str, Value>::new(),
+                    config.notifications.timeout_ms,
+                ),
+            )
+            .await?;
+        self.last_notification_id = reply.body().deserialize::<u32>()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::Duration};
+
+    use tokio::net::UnixListener;
+    use zbus::{interface, Guid};
+
+    use crate::config::{overrides::Overrides, reload::LiveConfig};
+
+    use super::*;
+
+    /// Answers `Hello`, which clients send to the bus after connecting
+    struct MockBus;
+
+    #[interface(name = "org.freedesktop.DBus")]
+    impl MockBus {
+        fn hello(&self) -> String {
+            ":1.1".to_owned()
+        }
+    }
+
+    /// Notification daemon that forwards received summaries and bodies
+    struct MockDaemon(flume::Sender<(u32, String, String)>);
+
+    #[interface(name = "org.freedesktop.Notifications")]
+    impl MockDaemon {
+        #[allow(clippy::too_many_arguments)]
+        fn notify(
+            &self,
+            _app_name: String,
+            replaces_id: u32,
+            _app_icon: String,
+            summary: String,
+            body: String,
+            _actions: Vec<String>,
+            _hints: HashMap<String, zbus::zvariant::OwnedValue>,
+            _expire_timeout: i32,
+        ) -> u32 {
+            let _ = self.0.send((replaces_id, summary, body));
+            replaces_id + 1
+        }
+    }
+
+    fn metadata(name: &str) -> TrackMetadata {
+        TrackMetadata {
+            track_name: name.to_owned(),
+            track_artists: vec!["Artist".to_owned()],
+            track_album: "Album".to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn notifies_mock_daemon_on_private_bus() {
+        let socket =
+            std::env::temp_dir().join(format!("verses-notify-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).unwrap();
+
+        let (received_tx, received_rx) = flume::unbounded();
+        let daemon = tokio::task::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            Builder::unix_stream(stream)
+                .server(Guid::generate())
+                .unwrap()
+                .p2p()
+                .serve_at("/org/freedesktop/DBus", MockBus)
+                .unwrap()
+                .serve_at(NOTIFICATIONS_PATH, MockDaemon(received_tx))
+                .unwrap()
+                .build()
+                .await
+                .unwrap()
+        });
+
+        let source = format!(
+            "[notifications]\nenabled = true\nbus_address = \"unix:path={}\"",
+            socket.display()
+        );
+        let config = VersesConfig::read_from_str(
+            &source,
+            &PathBuf::from("config.toml"),
+            &Overrides::default(),
+            None,
+        )
+        .await
+        .unwrap();
+        let config = LiveConfig::new(config, PathBuf::from("config.toml"), Overrides::default());
+
+        let (events_tx, events_rx) = flume::unbounded();
+        let notifier = tokio::task::spawn(async move {
+            DesktopNotifier::default()
+                .run_listener(events_rx, config)
+                .await
+        });
+        events_tx
+            .send(StatusEvent::NewTrackNoLyrics {
+                metadata: metadata("First"),
+            })
+            .unwrap();
+        events_tx
+            .send(StatusEvent::NewTrack {
+                metadata: metadata("Second"),
+                new_lyrics: Default::default(),
+            })
+            .unwrap();
+
+        let timeout = Duration::from_secs(5);
+        let first = tokio::time::timeout(timeout, received_rx.recv_async())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            first,
+            (
+                0,
+                "First".to_owned(),
+                "Artist • Album\n(no synchronized lyrics)".to_owned()
+            )
+        );
+        // the second notification replaces the first one
+        let second = tokio::time::timeout(timeout, received_rx.recv_async())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            second,
+            (1, "Second".to_owned(), "Artist • Album".to_owned())
+        );
+
+        drop(events_tx);
+        notifier.await.unwrap().unwrap();
+        drop(daemon);
+        let _ = std::fs::remove_file(&socket);
+    }
+
+    #[tokio::test]
+    async fn reports_unreachable_bus() {
+        let source = "[notifications]\nbus_address = \"unix:path=/nonexistent/verses.sock\"";
+        let path = PathBuf::from("config.toml");
+        let config = VersesConfig::read_from_str(source, &path, &Overrides::default(), None)
+            .await
+            .unwrap();
+        let config = LiveConfig::new(config, path, Overrides::default());
+
+        let (_events_tx, events_rx) = flume::unbounded();
+        let err = DesktopNotifier::default()
+            .run_listener(events_rx, config)
+            .await
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("could not connect to D-Bus"));
+    }
+}
@@ -0,0 +1,148 @@
+use image::RgbImage;
+use ratatui::style::Color;
+
+use crate::config::{ThemeColor, ThemeDynamic, VersesConfig};
+
+use super::line_style::to_rgb;
+
+/// Pixels are grouped into buckets by the top bits of each channel
+const BUCKET_BITS: u32 = 4;
+
+/// Minimal hue distance between the dominant and the accent color, in degrees
+const MIN_ACCENT_HUE_DISTANCE: f32 = 30.0;
+
+/// Colors derived from an album cover
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Palette {
+    /// The most common color
+    pub dominant: (u8, u8, u8),
+    /// A vivid color standing out from the dominant one
+    pub accent: (u8, u8, u8),
+}
+
+impl Palette {
+    pub fn from_image(image: &RgbImage) -> Option<Self> {
+        let levels = 1usize << BUCKET_BITS;
+        // sum of each channel and the amount of pixels in every bucket
+        let mut buckets = vec![([0u64; 3], 0u64); levels * levels * levels];
+        for pixel in image.pixels() {
+            let [r, g, b] = pixel.0;
+            let level = |it: u8| (it >> (8 - BUCKET_BITS)) as usize;
+            let bucket = &mut buckets[(level(r) * levels + level(g)) * levels + level(b)];
+            bucket.0[0] += r as u64;
+            bucket.0[1] += g as u64;
+            bucket.0[2] += b as u64;
+            bucket.1 += 1;
+        }
+        let colors = buckets
+            .into_iter()
+            .filter(|(_, count)| *count > 0)
+            .map(|(sum, count)| {
+                let avg = |it: u64| (it / count) as u8;
+                ((avg(sum[0]), avg(sum[1]), avg(sum[2])), count)
+            })
+            .collect::<Vec<_>>();
+
+        let dominant = colors.iter().max_by_key(|it| it.1)?.0;
+        let (dominant_hue, _) = hue_saturation(dominant);
+        // vivid and common colors are preferred, with a distinct hue if possible
+        let score = |color: (u8, u8, u8), count: u64| {
+            let (_, saturation) = hue_saturation(color);
+            count as f32 * (0.1 + saturation)
+        };
+        let best = |colors: &mut dyn Iterator<Item = &((u8, u8, u8), u64)>| {
+            colors
+                .max_by(|a, b| score(a.0, a.1).total_cmp(&score(b.0, b.1)))
+                .map(|it| it.0)
+        };
+        let accent = best(&mut colors.iter().filter(|(color, _)| {
+            let (hue, saturation) = hue_saturation(*color);
+            saturation > 0.2 && hue_distance(hue, dominant_hue) >= MIN_ACCENT_HUE_DISTANCE
+        }))
+        .or_else(|| best(&mut colors.iter()))
+        .unwrap_or(dominant);
+        Some(Self { dominant, accent })
+    }
+
+    /// Copy of the config with theme colors replaced by the palette,
+    /// adjusted to be readable against the background
+    pub fn apply(&self, cfg: &VersesConfig) -> VersesConfig {
+        let dynamic = &cfg.theme.dynamic;
+        let readable = |color| ThemeColor(readable_color(color, dynamic));
+        let mut cfg = cfg.clone();
+        if dynamic.lyrics {
+            cfg.theme.lyrics.active_text_color = readable(self.accent);
+            cfg.theme.lyrics.inactive_text_color = readable(self.dominant);
+        }
+        if dynamic.progress {
+            cfg.theme.progress_bar.color = readable(self.accent);
+        }
+        cfg
+    }
+}
+
+/// Lightens or darkens the color until it has enough contrast with the background
+fn readable_color(color: (u8, u8, u8), cfg: &ThemeDynamic) -> Color {
+    let background = to_rgb(cfg.background.0).unwrap_or((0, 0, 0));
+    let target = if luminance(background) < 0.5 {
+        (255, 255, 255)
+    } else {
+        (0, 0, 0)
+    };
+    let mut adjusted = color;
+    for step in 0..=20 {
+        adjusted = mix(color, target, step as f32 / 20.0);
+        if contrast(adjusted, background) >= cfg.min_contrast {
+            break;
+        }
+    }
+    Color::Rgb(adjusted.0, adjusted.1, adjusted.2)
+}
+
+fn mix(from: (u8, u8, u8), to: (u8, u8, u8), factor: f32) -> (u8, u8, u8) {
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * factor).round() as u8;
+    (mix(from.0, to.0), mix(from.1, to.1), mix(from.2, to.2))
+}
+
+/// Relative luminance, as defined by WCAG
+fn luminance((r, g, b): (u8, u8, u8)) -> f32 {
+    let channel = |it: u8| {
+        let it = it as f32 / 255.0;
+        if it <= 0.03928 {
+            it / 12.92
+        } else {
+            ((it + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * channel(r) + 0.7152 * channel(g) + 0.0722 * channel(b)
+}
+
+/// Contrast ratio between two colors, as defined by WCAG
+fn contrast(a: (u8, u8, u8), b: (u8, u8, u8)) -> f32 {
+    let (a, b) = (luminance(a), luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// Hue in degrees and HSV saturation of the color
+fn hue_saturation((r, g, b): (u8, u8, u8)) -> (f32, f32) {
+    let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+    if delta == 0.0 {
+        return (0.0, 0.0);
+    }
+    let hue = if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    (hue, delta / max)
+}
+
+fn hue_distance(a: f32, b: f32) -> f32 {
+    let distance = (a - b).abs();
+    distance.min(360.0 - distance)
+}
@@ -0,0 +1,501 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, bail};
+use rhai::{
+    module_resolvers::DummyModuleResolver, CallFnOptions, Dynamic, Engine, FuncArgs, Map, Scope,
+    AST,
+};
+
+use crate::{
+    config::{reload::SharedConfig, PluginConfiguration},
+    event::{StatusEvent, TrackMetadata},
+};
+
+use super::{handler::VersesListener, Lyrics};
+
+/// Plugin is disabled after failing this many times in a row
+const MAX_CONSECUTIVE_FAILURES: u32 = 3;
+
+const TRANSFORM_LINE_FN: &str = "transform_line";
+const INFO_FIELDS_FN: &str = "info_fields";
+const ON_EVENT_FN: &str = "on_event";
+const INIT_FN: &str = "init";
+
+/// Hosts user Rhai scripts, loaded from the plugins directory.
+///
+/// Plugins may define any of these functions:
+/// * `transform_line(words, language)` - returns the new text of a lyric line
+/// * `info_fields(track)` - returns a map of additional fields shown in the info panel
+/// * `on_event(name, data)` - called on each playback event
+/// * `init()` - called once after the plugin is loaded
+///
+/// Each plugin has its own state, accessible through `this` in all of these functions.
+/// Scripts run synchronously, so the host is used from blocking threads to keep them off the async workers
+pub struct PluginHost {
+    engine: Engine,
+    plugins: Vec<Plugin>,
+    /// Errors of plugins disabled since the last [`PluginHost::take_errors`]
+    errors: Mutex<Vec<String>>,
+}
+
+struct Plugin {
+    name: String,
+    ast: AST,
+    state: Mutex<PluginState>,
+}
+
+struct PluginState {
+    this: Dynamic,
+    failures: u32,
+    disabled: bool,
+}
+
+impl std::fmt::Debug for PluginHost {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PluginHost")
+            .field("plugins", &self.names().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Plugin {
+    fn defines(&self, fn_name: &str) -> bool {
+        self.ast.iter_functions().any(|it| it.name == fn_name)
+    }
+}
+
+impl PluginHost {
+    pub fn empty() -> Self {
+        Self {
+            engine: Engine::new_raw(),
+            plugins: Vec::new(),
+            errors: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Loads all `.rhai` scripts from the directory. Scripts that failed
+    /// to load are skipped and returned as errors alongside the host
+    pub fn load(dir: &Path, cfg: &PluginConfiguration) -> (Self, Vec<anyhow::Error>) {
+        if !cfg.enabled || !dir.exists() {
+            return (Self::empty(), Vec::new());
+        }
+
+        let engine = sandboxed_engine(cfg);
+        let mut host = Self {
+            engine,
+            plugins: Vec::new(),
+            errors: Mutex::new(Vec::new()),
+        };
+        let mut errors = Vec::new();
+
+        let mut paths = match std::fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(|it| it.ok().map(|it| it.path()))
+                .filter(|it| it.extension().map(|ext| ext == "rhai").unwrap_or(false))
+                .collect::<Vec<PathBuf>>(),
+            Err(err) => return (host, vec![err.into()]),
+        };
+        // loading in a predictable order, since transforms are chained
+        paths.sort();
+
+        for path in paths {
+            match host.load_plugin(&path) {
+                Ok(plugin) => host.plugins.push(plugin),
+                Err(err) => errors.push(err.context(format!("Failed to load plugin {path:?}"))),
+            }
+        }
+
+        (host, errors)
+    }
+
+    fn load_plugin(&self, path: &Path) -> anyhow::Result<Plugin> {
+        let name = path
+            .file_stem()
+            .map(|it| it.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let ast = self
+            .engine
+            .compile(std::fs::read_to_string(path)?)
+            .map_err(|err| anyhow!("{err}"))?;
+
+        let plugin = Plugin {
+            name,
+            ast,
+            state: Mutex::new(PluginState {
+                this: Dynamic::from_map(Map::new()),
+                failures: 0,
+                disabled: false,
+            }),
+        };
+        if plugin.defines(INIT_FN) {
+            let mut state = plugin.state.lock().unwrap();
+            if let Err(err) = self.call_unchecked::<Dynamic>(&plugin, &mut state, INIT_FN, ()) {
+                bail!("{err}");
+            }
+        }
+        Ok(plugin)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.plugins.iter().map(|it| it.name.as_str())
+    }
+
+    /// Errors of plugins that were disabled since the last call
+    pub fn take_errors(&self) -> Vec<String> {
+        std::mem::take(&mut self.errors.lock().unwrap())
+    }
+
+    fn handles_events(&self) -> bool {
+        self.plugins.iter().any(|it| it.defines(ON_EVENT_FN))
+    }
+
+    /// Passes each lyric line through the `transform_line` function of every plugin
+    pub fn transform_lyrics(&self, lyrics: &mut Lyrics) {
+        for plugin in self
+            .plugins
+            .iter()
+            .filter(|it| it.defines(TRANSFORM_LINE_FN))
+        {
+            for line in lyrics.lines.iter_mut() {
+                let args = (line.words.clone(), lyrics.language.clone());
+                if let Some(words) = self.call::<String>(plugin, TRANSFORM_LINE_FN, args) {
+                    line.words = words;
+                }
+            }
+        }
+    }
+
+    /// Collects additional info panel fields from the `info_fields` function of every plugin
+    pub fn info_fields(
+        &self,
+        metadata: &TrackMetadata,
+        lyrics: Option<&Lyrics>,
+    ) -> Vec<(String, String)> {
+        let track = serde_json::json!({
+            "name": metadata.track_name,
+            "artists": metadata.track_artists,
+            "album": metadata.track_album,
+            "genres": metadata.artist_genres,
+            "popularity": metadata.popularity,
+            "duration_ms": metadata.track_duration.as_millis() as u64,
+            "has_lyrics": lyrics.is_some(),
+            "language": lyrics.map(|it| it.language.clone()).unwrap_or_default(),
+        });
+        let track = if let Ok(track) = rhai::serde::to_dynamic(track) {
+            track
+        } else {
+            return Vec::new();
+        };
+
+        let mut fields = Vec::new();
+        for plugin in self.plugins.iter() {
+            if plugin.state.lock().unwrap().disabled {
+                fields.push((
+                    format!("Plugin {}", plugin.name),
+                    "disabled after errors".to_owned(),
+                ));
+                continue;
+            }
+            if !plugin.defines(INFO_FIELDS_FN) {
+                continue;
+            }
+            if let Some(map) = self.call::<Map>(plugin, INFO_FIELDS_FN, (track.clone(),)) {
+                fields.extend(
+                    map.into_iter()
+                        .map(|(key, value)| (key.to_string(), value.to_string())),
+                );
+            }
+        }
+        fields
+    }
+
+    fn dispatch_event(&self, event: &StatusEvent) {
+        let (name, data) = match event {
+            StatusEvent::NewTrack {
+                metadata,
+                new_lyrics,
+            } => (
+                "new_track",
+                serde_json::json!({
+                    "name": metadata.track_name,
+                    "artists": metadata.track_artists,
+                    "album": metadata.track_album,
+                    "language": new_lyrics.language,
+                }),
+            ),
+            StatusEvent::NewTrackNoLyrics { metadata } => (
+                "no_lyrics",
+                serde_json::json!({
+                    "name": metadata.track_name,
+                    "artists": metadata.track_artists,
+                    "album": metadata.track_album,
+                }),
+            ),
+            StatusEvent::SwitchLyricLine { new_line } => {
+                ("line_change", serde_json::json!({ "line": new_line }))
+            }
+            StatusEvent::TrackProgress { new_progress_ms } => (
+                "progress",
+                serde_json::json!({ "progress_ms": new_progress_ms }),
+            ),
+            StatusEvent::PlaybackState { is_playing } => (
+                "playback_state",
+                serde_json::json!({ "is_playing": is_playing }),
+            ),
+            // images and errors are not passed to scripts
+            StatusEvent::AlbumArt { .. } | StatusEvent::Error { .. } => return,
+        };
+        let data = if let Ok(data) = rhai::serde::to_dynamic(data) {
+            data
+        } else {
+            return;
+        };
+
+        for plugin in self.plugins.iter().filter(|it| it.defines(ON_EVENT_FN)) {
+            self.call::<Dynamic>(plugin, ON_EVENT_FN, (name.to_owned(), data.clone()));
+        }
+    }
+
+    /// Calls a plugin function, isolating any errors to this plugin.
+    /// Returns `None` if the call failed or the plugin is disabled
+    fn call<T: Clone + Send + Sync + 'static>(
+        &self,
+        plugin: &Plugin,
+        fn_name: &str,
+        args: impl FuncArgs,
+    ) -> Option<T> {
+        let mut state = plugin.state.lock().unwrap();
+        if state.disabled {
+            return None;
+        }
+        match self.call_unchecked(plugin, &mut state, fn_name, args) {
+            Ok(value) => {
+                state.failures = 0;
+                Some(value)
+            }
+            Err(err) => {
+                state.failures += 1;
+                if state.failures >= MAX_CONSECUTIVE_FAILURES {
+                    state.disabled = true;
+                    self.errors.lock().unwrap().push(format!(
+                        "Plugin `{}` was disabled after {} failures in a row: {err}",
+                        plugin.name, state.failures
+                    ));
+                }
+                None
+            }
+        }
+    }
+
+    fn call_unchecked<T: Clone + Send + Sync + 'static>(
+        &self,
+        plugin: &Plugin,
+        state: &mut PluginState,
+        fn_name: &str,
+        args: impl FuncArgs,
+    ) -> Result<T, Box<rhai::EvalAltResult>> {
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut state.this);
+        self.engine
+            .call_fn_with_options(options, &mut Scope::new(), &plugin.ast, fn_name, args)
+    }
+}
+
+fn sandboxed_engine(cfg: &PluginConfiguration) -> Engine {
+    let mut engine = Engine::new();
+    // Rhai has no file system or network access by default,
+    // but modules can still be imported from files
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.disable_symbol("eval");
+    engine.set_max_operations(cfg.max_operations);
+    engine.set_max_call_levels(32);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(64 * 1024);
+    engine.set_max_array_size(10_000);
+    engine.set_max_map_size(10_000);
+    // printing would break the terminal UI
+    engine.on_print(|_| {});
+    engine.on_debug(|_, _, _| {});
+    engine
+}
+
+/// Passes playback events to the `on_event` function of plugins
+pub struct PluginEventListener {
+    host: Arc<PluginHost>,
+}
+
+impl PluginEventListener {
+    /// Returns `None` if no plugin handles events
+    pub fn new(host: Arc<PluginHost>) -> Option<Self> {
+        if host.handles_events() {
+            Some(Self { host })
+        } else {
+            None
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl VersesListener for PluginEventListener {
+    async fn run_listener(
+        &mut self,
+        events: flume::Receiver<StatusEvent>,
+        _config: SharedConfig,
+    ) -> anyhow::Result<()> {
+        while let Ok(event) = events.recv_async().await {
+            let host = self.host.clone();
+            tokio::task::spawn_blocking(move || host.dispatch_event(&event)).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verses::{LyricLine, LyricSyncType};
+
+    /// Directory with plugin scripts, removed after the test
+    struct PluginDir(PathBuf);
+
+    impl PluginDir {
+        fn new(name: &str, scripts: &[(&str, &str)]) -> Self {
+            let dir = std::env::temp_dir().join(format!("verses-{name}-{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            for (file, source) in scripts {
+                std::fs::write(dir.join(file), source).unwrap();
+            }
+            Self(dir)
+        }
+
+        fn load(&self) -> (PluginHost, Vec<anyhow::Error>) {
+            let cfg = PluginConfiguration {
+                enabled: true,
+                max_operations: 10_000,
+            };
+            PluginHost::load(&self.0, &cfg)
+        }
+    }
+
+    impl Drop for PluginDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn lyrics(words: &str) -> Lyrics {
+        Lyrics {
+            sync_type: LyricSyncType::LineSynced,
+            lines: vec![LyricLine {
+                start_time_ms: 0,
+                words: words.to_owned(),
+            }],
+            language: "en".to_owned(),
+        }
+    }
+
+    const UPPERCASE: &str = "fn transform_line(words, language) { words.to_upper() }";
+
+    #[test]
+    fn runaway_script_does_not_stop_other_plugins() {
+        let dir = PluginDir::new(
+            "plugin-runaway",
+            &[
+                (
+                    "a_loop.rhai",
+                    "fn transform_line(words, language) { loop {} }",
+                ),
+                ("b_upper.rhai", UPPERCASE),
+            ],
+        );
+        let (host, errors) = dir.load();
+        assert!(errors.is_empty());
+
+        for _ in 0..MAX_CONSECUTIVE_FAILURES {
+            let mut lyrics = lyrics("hello");
+            host.transform_lyrics(&mut lyrics);
+            assert_eq!(lyrics.lines[0].words, "HELLO");
+        }
+        let errors = host.take_errors();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("Plugin `a_loop` was disabled"));
+    }
+
+    #[test]
+    fn imports_fail_in_isolation() {
+        let dir = PluginDir::new(
+            "plugin-import",
+            &[
+                (
+                    "a_import.rhai",
+                    r#"fn transform_line(words, language) { import "os" as os; words }"#,
+                ),
+                ("b_upper.rhai", UPPERCASE),
+            ],
+        );
+        let (host, errors) = dir.load();
+        assert!(errors.is_empty());
+
+        for _ in 0..MAX_CONSECUTIVE_FAILURES {
+            let mut lyrics = lyrics("hello");
+            host.transform_lyrics(&mut lyrics);
+            assert_eq!(lyrics.lines[0].words, "HELLO");
+        }
+        let errors = host.take_errors();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("Plugin `a_import` was disabled"));
+    }
+
+    #[test]
+    fn scripts_using_eval_are_not_loaded() {
+        let dir = PluginDir::new(
+            "plugin-eval",
+            &[
+                (
+                    "a_eval.rhai",
+                    r#"fn transform_line(words, language) { eval("words") }"#,
+                ),
+                ("b_upper.rhai", UPPERCASE),
+            ],
+        );
+        let (host, errors) = dir.load();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(host.names().collect::<Vec<_>>(), ["b_upper"]);
+    }
+
+    #[test]
+    fn disables_plugin_after_consecutive_failures() {
+        let dir = PluginDir::new(
+            "plugin-disable",
+            &[("failing.rhai", r#"fn info_fields(track) { throw "oops" }"#)],
+        );
+        let (host, errors) = dir.load();
+        assert!(errors.is_empty());
+
+        let metadata = TrackMetadata::default();
+        for _ in 0..MAX_CONSECUTIVE_FAILURES {
+            assert!(host.info_fields(&metadata, None).is_empty());
+        }
+        assert_eq!(
+            host.info_fields(&metadata, None),
+            [(
+                "Plugin failing".to_owned(),
+                "disabled after errors".to_owned()
+            )]
+        );
+
+        let errors = host.take_errors();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("Plugin `failing` was disabled"));
+        assert!(host.take_errors().is_empty());
+    }
+}
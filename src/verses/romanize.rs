@@ -0,0 +1,54 @@
+use std::sync::Arc;
+
+use deunicode::{deunicode, deunicode_with_tofu};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::config::VersesConfig;
+
+lazy_static! {
+    static ref UNEPXECTED_CAMEL_CASE_REGEX: Regex = Regex::new("[a-z][A-Z]").unwrap();
+}
+
+/// Whether lyrics in the provided language should be romanized
+#[inline]
+pub fn should_romanize(language: &str, cfg: &Arc<VersesConfig>) -> bool {
+    language != "en"
+        && cfg.general.romanize_unicode
+        && !cfg.general.romanize_exclude.iter().any(|it| it == language)
+}
+
+/// Romanizes a single lyric line, returning `None` if romanization
+/// did not change anything
+pub fn romanize_line(words: &str) -> Option<String> {
+    let romanized = deunicode_with_tofu(words, "[?]");
+    if romanized == words {
+        // in some cases, romanization is not needed
+        return None;
+    }
+    Some(
+        UNEPXECTED_CAMEL_CASE_REGEX
+            .replace_all(&romanized, |captures: &regex::Captures| {
+                captures
+                    .iter()
+                    .filter(Option::is_some)
+                    .map(|it| it.unwrap().as_str().to_lowercase())
+                    .collect::<String>()
+            })
+            .into_owned(),
+    )
+}
+
+#[inline]
+pub fn maybe_romanize_str(name: &str, language: &str, cfg: &Arc<VersesConfig>) -> String {
+    if cfg.general.romanize_track_names && should_romanize(language, cfg) {
+        let romanized = deunicode(name);
+        if romanized == name {
+            romanized
+        } else {
+            format!("{name} ({romanized})")
+        }
+    } else {
+        name.to_owned()
+    }
+}
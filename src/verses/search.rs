@@ -0,0 +1,159 @@
+use std::ops::Range;
+
+use ratatui::prelude::*;
+
+/// Incremental search within the displayed lyrics
+#[derive(Debug, Clone, Default)]
+pub struct LyricsSearch {
+    query: String,
+    /// Whether the search prompt is being typed into
+    pub editing: bool,
+    /// Rows of the lyrics text containing a match, updated on each render
+    matches: Vec<usize>,
+    /// Index of the selected match. `None` selects the first match after the active line
+    selected: Option<usize>,
+}
+
+impl LyricsSearch {
+    /// Opens an empty search prompt
+    pub fn start() -> Self {
+        Self {
+            editing: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn push(&mut self, char: char) {
+        self.query.push(char);
+        self.selected = None;
+    }
+
+    pub fn pop(&mut self) {
+        self.query.pop();
+        self.selected = None;
+    }
+
+    pub fn next(&mut self) {
+        if !self.matches.is_empty() {
+            self.selected = Some(self.selected.map(|it| it + 1).unwrap_or(0) % self.matches.len());
+        }
+    }
+
+    pub fn previous(&mut self) {
+        if !self.matches.is_empty() {
+            let len = self.matches.len();
+            self.selected = Some(self.selected.map(|it| it + len - 1).unwrap_or(0) % len);
+        }
+    }
+
+    /// Updates matches for the freshly rendered text and returns the row of the selected match
+    pub fn update_matches(&mut self, text: &[Line], active_row: usize) -> Option<usize> {
+        self.matches = text
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| {
+                line.spans
+                    .iter()
+                    .any(|span| !match_ranges(&span.content, &self.query).is_empty())
+            })
+            .map(|(row, _)| row)
+            .collect();
+        if self.matches.is_empty() {
+            self.selected = None;
+            return None;
+        }
+        let selected = match self.selected {
+            Some(selected) => selected.min(self.matches.len() - 1),
+            None => self
+                .matches
+                .iter()
+                .position(|row| *row >= active_row)
+                .unwrap_or(0),
+        };
+        self.selected = Some(selected);
+        Some(self.matches[selected])
+    }
+
+    /// Status shown in the search prompt, e.g. `2/5`
+    pub fn status(&self) -> String {
+        match self.selected {
+            Some(selected) => format!("{}/{}", selected + 1, self.matches.len()),
+            None if self.query.is_empty() => String::new(),
+            None => "no matches".to_owned(),
+        }
+    }
+
+    /// Highlights all matches in a single line of text.
+    /// Matches in the selected row are highlighted in bold
+    pub fn highlight<'a>(&self, line: Line<'a>, row: usize) -> Line<'a> {
+        if self.query.is_empty() {
+            return line;
+        }
+        let mut modifier = Modifier::REVERSED;
+        if self.selected.map(|it| self.matches.get(it)) == Some(Some(&row)) {
+            modifier |= Modifier::BOLD;
+        }
+
+        let mut spans = Vec::with_capacity(line.spans.len());
+        for span in line.spans {
+            let ranges = match_ranges(&span.content, &self.query);
+            if ranges.is_empty() {
+                spans.push(span);
+                continue;
+            }
+            let mut last = 0;
+            for range in ranges {
+                if range.start > last {
+                    spans.push(Span::styled(
+                        span.content[last..range.start].to_owned(),
+                        span.style,
+                    ));
+                }
+                spans.push(Span::styled(
+                    span.content[range.clone()].to_owned(),
+                    span.style.add_modifier(modifier),
+                ));
+                last = range.end;
+            }
+            if last < span.content.len() {
+                spans.push(Span::styled(span.content[last..].to_owned(), span.style));
+            }
+        }
+        Line {
+            spans,
+            alignment: line.alignment,
+        }
+    }
+}
+
+/// Byte ranges of case-insensitive, non-overlapping occurrences of the query in the text
+fn match_ranges(text: &str, query: &str) -> Vec<Range<usize>> {
+    let query = query.chars().collect::<Vec<_>>();
+    if query.is_empty() {
+        return Vec::new();
+    }
+    let chars = text.char_indices().collect::<Vec<_>>();
+    let mut ranges = Vec::new();
+    let mut idx = 0;
+    while idx + query.len() <= chars.len() {
+        let is_match = chars[idx..idx + query.len()]
+            .iter()
+            .zip(query.iter())
+            .all(|((_, char), other)| char.to_lowercase().eq(other.to_lowercase()));
+        if is_match {
+            let end = chars
+                .get(idx + query.len())
+                .map(|(pos, _)| *pos)
+                .unwrap_or(text.len());
+            ranges.push(chars[idx].0..end);
+            idx += query.len();
+        } else {
+            idx += 1;
+        }
+    }
+    ranges
+}
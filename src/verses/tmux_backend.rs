@@ -0,0 +1,244 @@
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use handlebars::Handlebars;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{reload::SharedConfig, VersesConfig},
+    event::PlaybackCommand,
+    paths::{self, write_atomic},
+};
+
+use super::{
+    format::{fmt_duration, template_registry, truncate_width},
+    handler::{LyricsTracker, SyncTracker, VersesBackend},
+    romanize::{maybe_romanize_str, romanize_line, should_romanize},
+    LyricSyncType,
+};
+
+/// Default handlebars format of the tmux status line
+pub const DEFAULT_STATUS_FORMAT: &str = "{{line}}";
+
+/// Snapshots older than this are considered stale,
+/// meaning that `verses tmux` is no longer running
+const SNAPSHOT_STALE_AFTER: Duration = Duration::from_secs(5);
+
+/// Backend that continuously writes the current lyric line into a file,
+/// which can then be displayed in the tmux status line
+#[derive(Debug, Clone)]
+pub struct TmuxBackend {
+    output: PathBuf,
+    format: String,
+    max_width: Option<usize>,
+}
+
+impl TmuxBackend {
+    pub fn new(output: PathBuf, format: String, max_width: Option<usize>) -> Self {
+        Self {
+            output,
+            format,
+            max_width,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl VersesBackend for TmuxBackend {
+    async fn run_backend(
+        &mut self,
+        tracker: SyncTracker,
+        config: SharedConfig,
+        _commands: flume::Sender<PlaybackCommand>,
+    ) -> anyhow::Result<()> {
+        let reg = template_registry();
+        let snapshot_path = paths::get()?.now_playing();
+        let mut last_rendered: Option<String> = None;
+        let mut reported_errors = 0;
+
+        loop {
+            let snapshot = {
+                let tracker = tracker.lock().unwrap();
+                for err in &tracker.errors[reported_errors..] {
+                    eprintln!("{err}");
+                }
+                reported_errors = tracker.errors.len();
+                StatusSnapshot::from_tracker(&tracker, &config.load())
+            };
+
+            let rendered = snapshot.render(&reg, &self.format, self.max_width)?;
+            if last_rendered.as_ref() != Some(&rendered) {
+                write_atomic(&self.output, rendered.as_bytes()).await?;
+                last_rendered = Some(rendered);
+            }
+            // snapshot is always rewritten so `verses ctl` knows we are still alive
+            write_atomic(&snapshot_path, &serde_json::to_vec(&snapshot)?).await?;
+
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    }
+}
+
+/// Current playback state, shared between `verses tmux` and `verses ctl`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StatusSnapshot {
+    pub track: String,
+    pub artists: Vec<String>,
+    pub album: String,
+    /// Current line, romanized if configured
+    pub line: String,
+    /// Current line exactly as provided by the lyrics API
+    pub original_line: String,
+    pub next_line: String,
+    pub progress_ms: u32,
+    pub duration_ms: u32,
+    /// Unix timestamp of the moment this snapshot was taken
+    pub updated_at: u64,
+}
+
+impl StatusSnapshot {
+    pub fn from_tracker(tracker: &LyricsTracker, cfg: &Arc<VersesConfig>) -> Self {
+        let romanize = should_romanize(&tracker.lyrics.language, cfg);
+        let line_at = |idx: isize| -> (String, String) {
+            if idx < 0 || tracker.lyrics.sync_type == LyricSyncType::Unsynced {
+                return Default::default();
+            }
+            match tracker.lyrics.lines.get(idx as usize) {
+                Some(line) => {
+                    let romanized = if romanize {
+                        romanize_line(&line.words)
+                    } else {
+                        None
+                    };
+                    (
+                        romanized.unwrap_or_else(|| line.words.clone()),
+                        line.words.clone(),
+                    )
+                }
+                None => Default::default(),
+            }
+        };
+        let (line, original_line) = line_at(tracker.current_line);
+        let (next_line, _) = line_at(tracker.current_line + 1);
+
+        Self {
+            track: maybe_romanize_str(
+                &tracker.track_data.track_name,
+                &tracker.lyrics.language,
+                cfg,
+            ),
+            artists: tracker.track_data.track_artists.clone(),
+            album: tracker.track_data.track_album.clone(),
+            line,
+            original_line,
+            next_line,
+            progress_ms: tracker.current_progress_ms,
+            duration_ms: tracker.track_data.track_duration.as_millis() as u32,
+            updated_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        }
+    }
+
+    /// Reads the snapshot written by a running `verses tmux` instance.
+    /// Returns `None` if there is no snapshot, or it is outdated
+    pub async fn read_latest() -> anyhow::Result<Option<Self>> {
+        let path = paths::get()?.now_playing();
+        if !path.exists() {
+            return Ok(None);
+        }
+        let snapshot: Self = serde_json::from_slice(&tokio::fs::read(path).await?)?;
+        let age = SystemTime::now()
+            .duration_since(UNIX_EPOCH + Duration::from_secs(snapshot.updated_at))
+            .unwrap_or_default();
+        if age > SNAPSHOT_STALE_AFTER {
+            Ok(None)
+        } else {
+            Ok(Some(snapshot))
+        }
+    }
+
+    /// Renders this snapshot using the provided handlebars format
+    pub fn render(
+        &self,
+        reg: &Handlebars,
+        format: &str,
+        max_width: Option<usize>,
+    ) -> anyhow::Result<String> {
+        let percent = (u64::from(self.progress_ms) * 100)
+            .checked_div(u64::from(self.duration_ms))
+            .unwrap_or(0);
+        let rendered = reg.render_template(
+            format,
+            &serde_json::json!({
+                "line": self.line,
+                "original_line": self.original_line,
+                "next_line": self.next_line,
+                "track": self.track,
+                "artists": self.artists,
+                "album": self.album,
+                "progress": fmt_duration(self.progress_ms),
+                "duration": fmt_duration(self.duration_ms),
+                "percent": percent,
+            }),
+        )?;
+        let rendered = match max_width {
+            Some(max_width) => truncate_width(&rendered, max_width),
+            None => rendered,
+        };
+        // `#` starts a format sequence in tmux
+        Ok(rendered.replace('#', "##"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(progress_ms: u32, duration_ms: u32) -> StatusSnapshot {
+        StatusSnapshot {
+            track: "Track #1".to_owned(),
+            artists: vec!["Artist".to_owned()],
+            line: "Current line".to_owned(),
+            progress_ms,
+            duration_ms,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn renders_progress_of_long_tracks() {
+        let reg = template_registry();
+        // 20 minutes into a 40 minute track
+        let rendered = snapshot(1_200_000, 2_400_000)
+            .render(&reg, "{{ progress }}/{{ duration }} {{ percent }}%", None)
+            .unwrap();
+        assert_eq!(rendered, "20:00/40:00 50%");
+    }
+
+    #[test]
+    fn renders_without_duration() {
+        let reg = template_registry();
+        let rendered = snapshot(1000, 0)
+            .render(&reg, "{{ percent }}%", None)
+            .unwrap();
+        assert_eq!(rendered, "0%");
+    }
+
+    #[test]
+    fn escapes_and_truncates() {
+        let reg = template_registry();
+        let snapshot = snapshot(0, 1000);
+        let rendered = snapshot.render(&reg, "{{ track }}", None).unwrap();
+        assert_eq!(rendered, "Track ##1");
+        let rendered = snapshot
+            .render(&reg, "{{ track }}: {{ line }}", Some(10))
+            .unwrap();
+        // `##` is displayed as a single `#` by tmux
+        assert_eq!(rendered, "Track ##1:…");
+    }
+}
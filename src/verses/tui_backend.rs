@@ -1,32 +1,58 @@
-use std::{borrow::Cow, io::Stdout, sync::Arc, time::Duration, vec};
+use std::{
+    io::Stdout,
+    sync::Arc,
+    time::{Duration, Instant},
+    vec,
+};
 
 use crossterm::{
-    event::{self, Event, KeyCode},
+    cursor::{MoveTo, RestorePosition, SavePosition},
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind,
+        KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+    },
+    style::Print,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use deunicode::{deunicode, deunicode_with_tofu};
-use handlebars::{handlebars_helper, Handlebars};
-use lazy_static::lazy_static;
+use image::RgbImage;
 use ratatui::{
     prelude::*,
     style::Stylize,
-    widgets::{Block, Borders, Gauge, Paragraph, Wrap},
+    widgets::{
+        block::{Position, Title},
+        Block, Borders, Clear, Gauge, Padding, Paragraph, Wrap,
+    },
     Frame, Terminal,
 };
-use regex::Regex;
+use unicode_width::UnicodeWidthStr;
 
-use crate::config::VersesConfig;
+use crate::{
+    config::{
+        keys::KeyAction, layout::PaneKind, reload::SharedConfig, GraphicsProtocol, ScrollMode,
+        VersesConfig,
+    },
+    event::PlaybackCommand,
+};
 
 use super::{
-    handler::{SyncTracker, VersesBackend},
+    album_art::{half_blocks, kitty_sequence, sixel_sequence},
+    format::{fmt_duration, template_registry, wrap_line},
+    handler::{LyricsTracker, SyncTracker, VersesBackend},
+    instrumental::InstrumentalBreak,
+    karaoke::big_text,
+    line_style::{supports_truecolor, LineStyler},
+    palette::Palette,
+    romanize::{maybe_romanize_str, romanize_line, should_romanize},
+    search::LyricsSearch,
+    ui_state::UiState,
     LyricSyncType,
 };
 
 type Term = Terminal<CrosstermBackend<Stdout>>;
+type TermFrame<'f> = Frame<'f, CrosstermBackend<Stdout>>;
 
-lazy_static! {
-    static ref UNEPXECTED_CAMEL_CASE_REGEX: Regex = Regex::new("[a-z][A-Z]").unwrap();
-}
+/// Config errors longer than this are cut off
+const MAX_ERROR_ROWS: usize = 3;
 
 #[derive(Debug, Clone)]
 pub struct TerminalUiBackend<'a> {
@@ -35,6 +61,43 @@ pub struct TerminalUiBackend<'a> {
 
     autoscroll_enabled: bool,
     scroll_amount: u16,
+    show_help: bool,
+    /// Start of the instrumental indicator animation
+    animation_start: Instant,
+    truecolor: bool,
+    /// Active line of the last rendered frame
+    active_line: isize,
+    previous_line: isize,
+    line_changed_at: Instant,
+    /// Whether the active line color is still being animated
+    in_transition: bool,
+    karaoke: bool,
+    ui_state: UiState,
+    search: Option<LyricsSearch>,
+    /// Scroll position of the last rendered frame
+    last_scroll_y: u16,
+    /// Lyric line clicked by the user, together with the name of its track
+    selected_line: Option<(String, usize)>,
+    /// Index of the lyric line displayed in each row of the last rendered frame
+    row_lines: Vec<usize>,
+    /// Name of the track `row_lines` were rendered for
+    row_lines_track: String,
+    /// Areas of the last rendered frame, used to locate mouse clicks
+    lyrics_area: Rect,
+    progress_area: Rect,
+    commands: Option<flume::Sender<PlaybackCommand>>,
+    graphics: GraphicsProtocol,
+    /// Album art to draw with a graphics protocol after the frame is rendered
+    pending_art: Option<(Rect, Arc<RgbImage>)>,
+    /// Area and image of the album art currently on the screen
+    drawn_art: Option<(Rect, Arc<RgbImage>)>,
+    /// Half block lines of the album art, along with the area and image they were made for
+    art_lines: Option<(Rect, Arc<RgbImage>, Vec<Line<'static>>)>,
+    /// Config with colors derived from the album art, along with the config and image it was made from.
+    /// Both are kept alive, so they can not be mistaken for new ones allocated at the same address
+    themed_config: Option<(Arc<VersesConfig>, Arc<RgbImage>, Arc<VersesConfig>)>,
+    /// Error of the last config reload, shown until the config is fixed
+    config_error: Option<Arc<String>>,
 }
 
 #[async_trait::async_trait]
@@ -42,11 +105,18 @@ impl<'a> VersesBackend for TerminalUiBackend<'a> {
     async fn run_backend(
         &mut self,
         tracker: SyncTracker,
-        config: Arc<VersesConfig>,
+        config: SharedConfig,
+        commands: flume::Sender<PlaybackCommand>,
     ) -> anyhow::Result<()> {
-        let mut terminal = setup_terminal()?;
+        self.ui_state = UiState::load().await;
+        self.commands = Some(commands);
+        let initial = config.load();
+        self.graphics = initial.album_art.protocol.detect();
+        let mut terminal = setup_terminal(initial.mouse.enabled)?;
 
         self.tui_loop(tracker, &mut terminal, config).await?;
+        self.pending_art = None;
+        self.draw_graphics(&mut terminal)?;
 
         restore_terminal(&mut terminal)
     }
@@ -59,6 +129,29 @@ impl<'a> Default for TerminalUiBackend<'a> {
             old_tracker_hash: 0,
             autoscroll_enabled: true,
             scroll_amount: 0,
+            show_help: false,
+            animation_start: Instant::now(),
+            truecolor: supports_truecolor(),
+            active_line: -1,
+            previous_line: -1,
+            line_changed_at: Instant::now(),
+            in_transition: false,
+            karaoke: false,
+            ui_state: UiState::default(),
+            search: None,
+            last_scroll_y: 0,
+            selected_line: None,
+            row_lines: Vec::new(),
+            row_lines_track: String::new(),
+            lyrics_area: Rect::default(),
+            progress_area: Rect::default(),
+            commands: None,
+            graphics: GraphicsProtocol::HalfBlocks,
+            pending_art: None,
+            drawn_art: None,
+            art_lines: None,
+            themed_config: None,
+            config_error: None,
         }
     }
 }
@@ -68,71 +161,415 @@ impl<'a> TerminalUiBackend<'a> {
         &mut self,
         tracker: SyncTracker,
         terminal: &mut Term,
-        cfg: Arc<VersesConfig>,
+        config: SharedConfig,
     ) -> anyhow::Result<()> {
+        let mut loaded = config.load();
         loop {
+            let cfg = config.load();
+            if !Arc::ptr_eq(&cfg, &loaded) {
+                // info panel is rebuilt with the new formats
+                self.old_tracker_hash = 0;
+                loaded = cfg.clone();
+            }
+            self.config_error = config.error();
             terminal.draw(|frame| self.handle_ui(&tracker, frame, &cfg))?;
-            if event::poll(Duration::from_millis(250))? {
-                if let Event::Key(key) = event::read()? {
-                    match key.code {
-                        KeyCode::Char('q') => break Ok(()),
-                        KeyCode::Char('a') => {
+            self.draw_graphics(terminal)?;
+            // redrawing more often to keep transitions smooth
+            let timeout = if self.in_transition { 30 } else { 250 };
+            if event::poll(Duration::from_millis(timeout))? {
+                let event = event::read()?;
+                if let Event::Mouse(mouse) = event {
+                    if !self.show_help {
+                        self.handle_mouse(&mouse, &tracker, &cfg);
+                    }
+                    continue;
+                }
+                if let Event::Key(key) = event {
+                    if key.kind != KeyEventKind::Press {
+                        continue;
+                    }
+                    if self.show_help && key.code == KeyCode::Esc {
+                        self.show_help = false;
+                        continue;
+                    }
+                    if self.search.is_none()
+                        && self.selected_line.is_some()
+                        && key.code == KeyCode::Esc
+                    {
+                        self.selected_line = None;
+                        continue;
+                    }
+                    if self.handle_search_key(&key) {
+                        continue;
+                    }
+                    match cfg.keys.action_for(&key) {
+                        Some(KeyAction::Quit) => break Ok(()),
+                        Some(KeyAction::ToggleAutoscroll) => {
                             self.autoscroll_enabled = !self.autoscroll_enabled;
                         }
-                        KeyCode::Char('j') | KeyCode::Down => {
+                        Some(KeyAction::ScrollDown) => {
+                            self.scroll_manually();
                             self.scroll_amount += 1;
                         }
-                        KeyCode::Char('k') | KeyCode::Up => {
+                        Some(KeyAction::ScrollUp) => {
+                            self.scroll_manually();
                             self.scroll_amount = (self.scroll_amount as i16 - 1).max(0) as u16;
                         }
-                        KeyCode::Char('r') => {
+                        Some(KeyAction::ResetScroll) => {
                             self.scroll_amount = 0;
                         }
-                        _ => continue,
+                        Some(KeyAction::ToggleHelp) => {
+                            self.show_help = !self.show_help;
+                        }
+                        Some(KeyAction::ToggleKaraoke) => {
+                            self.karaoke = !self.karaoke;
+                        }
+                        Some(KeyAction::CycleProfile) => {
+                            config.cycle_profile().await;
+                        }
+                        Some(KeyAction::ToggleInfo) => {
+                            self.ui_state.show_info = !self.ui_state.show_info;
+                            self.save_ui_state().await;
+                        }
+                        Some(KeyAction::ToggleProgress) => {
+                            self.ui_state.show_progress = !self.ui_state.show_progress;
+                            self.save_ui_state().await;
+                        }
+                        Some(KeyAction::ToggleBorders) => {
+                            self.ui_state.show_borders = !self.ui_state.show_borders;
+                            self.save_ui_state().await;
+                        }
+                        Some(KeyAction::Search) => {
+                            self.search = Some(LyricsSearch::start());
+                        }
+                        Some(KeyAction::NextMatch) => {
+                            if let Some(search) = &mut self.search {
+                                search.next();
+                            }
+                        }
+                        Some(KeyAction::PreviousMatch) => {
+                            if let Some(search) = &mut self.search {
+                                search.previous();
+                            }
+                        }
+                        None => continue,
                     }
                 }
             }
         }
     }
 
-    fn handle_ui(
-        &mut self,
-        tracker: &SyncTracker,
-        f: &mut Frame<CrosstermBackend<Stdout>>,
-        cfg: &Arc<VersesConfig>,
-    ) {
+    fn handle_ui(&mut self, tracker: &SyncTracker, f: &mut TermFrame, cfg: &Arc<VersesConfig>) {
         let size = f.size();
 
         let tracker = tracker.lock().unwrap();
+        let cfg = &self.themed_config(&tracker, cfg);
+
+        self.lyrics_area = Rect::default();
+        self.progress_area = Rect::default();
+        self.in_transition = false;
+        self.pending_art = None;
+        if self.karaoke {
+            self.render_karaoke(&tracker, f, size, cfg);
+            if self.show_help {
+                self.render_help(f, size, cfg);
+            }
+            self.render_error_banner(&tracker, f, size);
+            return;
+        }
+
+        // progress bar is moved into the lyrics title on short terminals
+        let progress_in_title = cfg.layout.breakpoints(size).short && self.ui_state.show_progress;
+        let mut hidden = self.ui_state.hidden_panes();
+        if !cfg.album_art.enabled || tracker.track_data.album_art.is_none() {
+            hidden.push(PaneKind::AlbumArt);
+        }
+        for (pane, area) in cfg.layout.split(size, &hidden) {
+            match pane {
+                PaneKind::AlbumArt => self.render_album_art(&tracker, f, area, cfg),
+                PaneKind::Lyrics => self.render_lyrics(&tracker, f, area, cfg, progress_in_title),
+                PaneKind::Info => self.render_info(&tracker, f, area, cfg),
+                PaneKind::Progress => self.render_progress(&tracker, f, area, cfg),
+                PaneKind::Status => self.render_status(&tracker, f, area, cfg),
+            }
+        }
 
+        if self.show_help {
+            self.render_help(f, size, cfg);
+        }
+        self.render_error_banner(&tracker, f, size);
+    }
+
+    /// Banner at the top of the screen, shown while the config file is invalid or after a listener stopped
+    fn render_error_banner(&self, tracker: &LyricsTracker, f: &mut TermFrame, size: Rect) {
+        let (prefix, error) = match (&self.config_error, tracker.errors.last()) {
+            (Some(error), _) => (
+                "Failed to reload config, using the previous one: ",
+                error.as_str(),
+            ),
+            (None, Some(error)) => ("", error.as_str()),
+            (None, None) => return,
+        };
+        // parse errors span several lines, which do not fit into the banner
+        let error = error.split_whitespace().collect::<Vec<_>>().join(" ");
+        let text = format!(" {prefix}{error}");
+        let width = size.width.max(1) as usize;
+        let height = (text.width() / width + 1).min(MAX_ERROR_ROWS) as u16;
+        let area = Rect::new(size.x, size.y, size.width, height.min(size.height));
+        let banner = Paragraph::new(text)
+            .style(Style::default().fg(Color::White).bg(Color::Red))
+            .wrap(Wrap { trim: false });
+        f.render_widget(Clear, area);
+        f.render_widget(banner, area);
+    }
+
+    /// Draws album art with the kitty or sixel protocol, which bypass the terminal buffer.
+    /// Images are only redrawn after they change, since they stay on the screen otherwise
+    fn draw_graphics(&mut self, terminal: &mut Term) -> anyhow::Result<()> {
+        let unchanged = match (&self.pending_art, &self.drawn_art) {
+            (Some((area, image)), Some((drawn_area, drawn_image))) => {
+                area == drawn_area && Arc::ptr_eq(image, drawn_image)
+            }
+            (None, None) => true,
+            _ => false,
+        };
+        if unchanged {
+            return Ok(());
+        }
+        match self.graphics {
+            GraphicsProtocol::Kitty => {
+                crossterm::execute!(terminal.backend_mut(), Print("\x1b_Ga=d,q=2\x1b\\"))?;
+            }
+            GraphicsProtocol::Sixel if self.drawn_art.is_some() => {
+                // sixel pixels are only removed by redrawing the whole screen,
+                // the new image is drawn after the next frame
+                terminal.clear()?;
+                self.drawn_art = None;
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        if let Some((area, image)) = &self.pending_art {
+            let sequence = match self.graphics {
+                GraphicsProtocol::Kitty => kitty_sequence(image, area.width, area.height)?,
+                GraphicsProtocol::Sixel => sixel_sequence(image, area.width, area.height),
+                _ => String::new(),
+            };
+            crossterm::execute!(
+                terminal.backend_mut(),
+                SavePosition,
+                MoveTo(area.x, area.y),
+                Print(sequence),
+                RestorePosition
+            )?;
+        }
+        self.drawn_art = self.pending_art.clone();
+        Ok(())
+    }
+
+    /// Config with the theme derived from the album art, if enabled.
+    /// Terminals without truecolor keep the configured theme
+    fn themed_config(
+        &mut self,
+        tracker: &LyricsTracker,
+        cfg: &Arc<VersesConfig>,
+    ) -> Arc<VersesConfig> {
+        let image = match &tracker.track_data.album_art {
+            Some(image) if cfg.theme.dynamic.enabled && self.truecolor => image,
+            _ => {
+                self.themed_config = None;
+                return cfg.clone();
+            }
+        };
+        match &self.themed_config {
+            Some((source, source_image, themed))
+                if Arc::ptr_eq(source, cfg) && Arc::ptr_eq(source_image, image) =>
+            {
+                themed.clone()
+            }
+            _ => {
+                let themed = match Palette::from_image(image) {
+                    Some(palette) => Arc::new(palette.apply(cfg)),
+                    None => cfg.clone(),
+                };
+                self.themed_config = Some((cfg.clone(), image.clone(), themed.clone()));
+                themed
+            }
+        }
+    }
+
+    fn handle_mouse(&mut self, mouse: &MouseEvent, tracker: &SyncTracker, cfg: &Arc<VersesConfig>) {
+        match mouse.kind {
+            MouseEventKind::ScrollDown => {
+                self.scroll_manually();
+                self.scroll_amount = self.scroll_amount.saturating_add(cfg.mouse.scroll_lines);
+            }
+            MouseEventKind::ScrollUp => {
+                self.scroll_manually();
+                self.scroll_amount = self.scroll_amount.saturating_sub(cfg.mouse.scroll_lines);
+            }
+            MouseEventKind::Down(MouseButton::Left) => {
+                let tracker = tracker.lock().unwrap();
+                if contains(self.lyrics_area, mouse.column, mouse.row) {
+                    // rows are from the last frame, the track may have changed since then
+                    if self.row_lines_track != tracker.track_data.track_name {
+                        return;
+                    }
+                    let row = self.last_scroll_y + (mouse.row - self.lyrics_area.y);
+                    let (line, position_ms) = match self
+                        .row_lines
+                        .get(row as usize)
+                        .and_then(|line| Some((*line, tracker.lyrics.lines.get(*line)?)))
+                    {
+                        Some((line, lyric)) => (line, lyric.start_time_ms),
+                        None => return,
+                    };
+                    self.selected_line = Some((tracker.track_data.track_name.clone(), line));
+                    if tracker.lyrics.sync_type == LyricSyncType::LineSynced {
+                        self.send_command(PlaybackCommand::Seek { position_ms }, cfg);
+                    }
+                } else if contains(self.progress_area, mouse.column, mouse.row) {
+                    let ratio = (mouse.column - self.progress_area.x) as f64
+                        / self.progress_area.width as f64;
+                    let duration_ms = tracker.track_data.track_duration.as_millis() as f64;
+                    let position_ms = (ratio * duration_ms) as u32;
+                    self.send_command(PlaybackCommand::Seek { position_ms }, cfg);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Disables autoscroll, continuing manual scrolling from the current position
+    fn scroll_manually(&mut self) {
+        if self.autoscroll_enabled {
+            self.autoscroll_enabled = false;
+            self.scroll_amount = self.last_scroll_y;
+        }
+    }
+
+    fn send_command(&self, command: PlaybackCommand, cfg: &Arc<VersesConfig>) {
+        if !cfg.playback.control {
+            return;
+        }
+        if let Some(commands) = &self.commands {
+            // dropping the command if the previous ones are still being sent
+            let _ = commands.try_send(command);
+        }
+    }
+
+    /// Handles keys typed into the search prompt, returns whether the key was consumed
+    fn handle_search_key(&mut self, key: &KeyEvent) -> bool {
+        let search = match &mut self.search {
+            Some(search) => search,
+            None => return false,
+        };
+        if key.code == KeyCode::Esc {
+            self.dismiss_search();
+            return true;
+        }
+        if !search.editing {
+            return false;
+        }
+        match key.code {
+            KeyCode::Enter if search.query().is_empty() => self.dismiss_search(),
+            KeyCode::Enter => search.editing = false,
+            KeyCode::Backspace => search.pop(),
+            KeyCode::Char(char)
+                if !key
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                search.push(char)
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn dismiss_search(&mut self) {
+        self.search = None;
+        // staying at the last match if autoscroll is disabled
+        self.scroll_amount = self.last_scroll_y;
+    }
+
+    async fn save_ui_state(&self) {
+        // failing to persist the state is not worth interrupting the user
+        let _ = self.ui_state.save().await;
+    }
+
+    fn borders(&self) -> Borders {
+        if self.ui_state.show_borders {
+            Borders::ALL
+        } else {
+            Borders::NONE
+        }
+    }
+
+    fn render_lyrics(
+        &mut self,
+        tracker: &LyricsTracker,
+        f: &mut TermFrame,
+        area: Rect,
+        cfg: &Arc<VersesConfig>,
+        progress_in_title: bool,
+    ) {
         let lyrics_top_text = maybe_romanize_str(
             &tracker.track_data.track_name,
             &tracker.lyrics.language,
             cfg,
         );
-        let lyrics_block = Block::default()
+        let mut lyrics_block = Block::default()
             .fg(cfg.theme.borders.lyrics_border_color.0)
             .title(Line::from(
                 lyrics_top_text.fg(cfg.theme.borders.lyrics_border_text_color.0),
             ))
-            .borders(Borders::ALL)
+            .borders(self.borders())
             .border_type(cfg.theme.borders.lyrics_border_style.0)
             .title_alignment(Alignment::Left);
+        if let Some(search) = &self.search {
+            let cursor = if search.editing { "▏" } else { "" };
+            lyrics_block = lyrics_block.title(
+                Title::from(Span::styled(
+                    format!("/{}{cursor} {}", search.query(), search.status()),
+                    Style::default().fg(cfg.theme.lyrics.active_text_color.0),
+                ))
+                .position(Position::Bottom)
+                .alignment(Alignment::Left),
+            );
+            if !self.ui_state.show_borders {
+                // keeping the prompt from covering the last line
+                lyrics_block = lyrics_block.padding(Padding::new(0, 0, 0, 1));
+            }
+        }
+        if progress_in_title {
+            let (_, label) = progress_label(tracker, cfg);
+            lyrics_block = lyrics_block.title(
+                Title::from(Span::styled(
+                    label,
+                    Style::default().fg(cfg.theme.progress_bar.color.0),
+                ))
+                .alignment(Alignment::Right),
+            );
+        }
 
-        // Layouts
-        let vertical_layout = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([Constraint::Percentage(95), Constraint::Percentage(5)].as_ref())
-            .split(size);
-
-        let horizontal_layout = Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints([Constraint::Percentage(75), Constraint::Percentage(26)].as_ref())
-            .split(vertical_layout[0]);
-
-        // Lyrics
         let current_line = tracker.current_line as usize;
-        let text = if tracker.lyrics.lines.is_empty() {
+        // source row of the active line, romanized lines take an additional row
+        let mut active_row = 0;
+        // lyric line of each source row
+        let mut source_lines = Vec::with_capacity(tracker.lyrics.lines.len());
+        // selection is reset once the track changes
+        if self
+            .selected_line
+            .as_ref()
+            .is_some_and(|(track, _)| *track != tracker.track_data.track_name)
+        {
+            self.selected_line = None;
+        }
+        let selected_line = self.selected_line.as_ref().map(|(_, line)| *line);
+        let mut text = if tracker.lyrics.lines.is_empty() {
+            source_lines.push(0);
             vec![Line::from(
                 "This song does not have synchronized lyrics :(".fg(cfg
                     .theme
@@ -141,100 +578,141 @@ impl<'a> TerminalUiBackend<'a> {
                     .0),
             )]
         } else {
-            let lines = if tracker.lyrics.language != "en"
-                && cfg.general.romanize_unicode
-                && !cfg
-                    .general
-                    .romanize_exclude
-                    .contains(&tracker.lyrics.language)
-            {
-                // attempting to romanize non-english lines
-                tracker
-                    .lyrics
-                    .lines
-                    .iter()
-                    .enumerate()
-                    .flat_map(|(idx, each)| {
-                        let romanized = deunicode_with_tofu(&each.words, "[?]");
-                        let other = romanized.clone();
-                        let replace = Cow::Owned(
-                            (*UNEPXECTED_CAMEL_CASE_REGEX.replace_all(
-                                &other,
-                                |captures: &regex::Captures| {
-                                    captures
-                                        .iter()
-                                        .filter(Option::is_some)
-                                        .map(|it| it.unwrap().as_str().to_lowercase())
-                                        .collect::<String>()
-                                },
-                            ))
-                            .to_owned(),
-                        );
-
-                        let fg_color = if idx == current_line
-                            && tracker.lyrics.sync_type != LyricSyncType::Unsynced
-                        {
-                            cfg.theme.lyrics.active_text_color.0
-                        } else {
-                            cfg.theme.lyrics.inactive_text_color.0
-                        };
-                        if romanized == each.words {
-                            // in some cases, romanization is not needed
-                            vec![Line::from(each.words.fg(fg_color))]
-                        } else {
-                            vec![
-                                Line::from(each.words.fg(fg_color)),
-                                Line::from(Span {
-                                    content: replace,
-                                    style: Style::default().fg(fg_color),
-                                }),
-                            ]
-                        }
-                    })
-                    .collect::<Vec<_>>()
-            } else {
-                tracker
-                    .lyrics
-                    .lines
-                    .iter()
-                    .enumerate()
-                    .map(|(idx, each)| {
-                        if idx == current_line
-                            && tracker.lyrics.sync_type != LyricSyncType::Unsynced
-                        {
-                            Line::from(each.words.fg(cfg.theme.lyrics.active_text_color.0))
-                        } else {
-                            Line::from(each.words.fg(cfg.theme.lyrics.inactive_text_color.0))
-                        }
-                    })
-                    .collect::<Vec<_>>()
+            if tracker.current_line != self.active_line {
+                self.previous_line = self.active_line;
+                self.active_line = tracker.current_line;
+                self.line_changed_at = Instant::now();
+            }
+            let styler = LineStyler {
+                theme: &cfg.theme.lyrics,
+                truecolor: self.truecolor,
+                synced: tracker.lyrics.sync_type != LyricSyncType::Unsynced,
+                current_line: tracker.current_line,
+                previous_line: self.previous_line,
+                since_change: self.line_changed_at.elapsed(),
             };
-            lines
+            self.in_transition = styler.in_transition();
+
+            let romanize = should_romanize(&tracker.lyrics.language, cfg);
+            let mut text = Vec::with_capacity(tracker.lyrics.lines.len());
+            for (idx, each) in tracker.lyrics.lines.iter().enumerate() {
+                let mut style = styler.style(idx);
+                if selected_line == Some(idx) {
+                    style = style.add_modifier(Modifier::UNDERLINED);
+                }
+                if idx == current_line {
+                    active_row = text.len();
+                }
+                text.push(Line::from(Span::styled(each.words.as_str(), style)));
+                source_lines.push(idx);
+                if romanize {
+                    // attempting to romanize non-english lines
+                    if let Some(romanized) = romanize_line(&each.words) {
+                        text.push(Line::from(Span::styled(romanized, style)));
+                        source_lines.push(idx);
+                    }
+                }
+            }
+            text
         };
 
-        let scroll_y =
-            if self.autoscroll_enabled && tracker.lyrics.sync_type == LyricSyncType::LineSynced {
-                let max_y_height = horizontal_layout[0].height as i16;
-                let y_offset = cfg.general.scroll_offset as i16;
-                (current_line as i16 - y_offset).clamp(0, (text.len() as i16 - max_y_height).max(0))
-                    as u16
-            } else {
-                self.scroll_amount
+        if let Some(indicator) = self.instrumental_indicator(tracker, cfg) {
+            // placing the indicator right after the active line
+            let (row, line) = match source_lines.iter().rposition(|it| *it == current_line) {
+                Some(row) => (row + 1, current_line),
+                None => (0, 0),
             };
+            text.insert(row, indicator);
+            source_lines.insert(row, line);
+        }
+
+        // autoscroll is suspended while searching
+        let search_row = self
+            .search
+            .as_mut()
+            .and_then(|search| search.update_matches(&text, active_row));
+        let text = match &self.search {
+            Some(search) => text
+                .into_iter()
+                .enumerate()
+                .map(|(row, line)| search.highlight(line, row))
+                .collect(),
+            None => text,
+        };
 
-        let lyrics_part = Paragraph::new(text)
+        // wrapping lines ourselves, so the amount of rendered rows is known exactly
+        let inner = lyrics_block.inner(area);
+        let mut rows = Vec::with_capacity(text.len());
+        let mut row_starts = Vec::with_capacity(text.len());
+        self.row_lines.clear();
+        self.row_lines_track
+            .clone_from(&tracker.track_data.track_name);
+        for (line, idx) in text.iter().zip(source_lines) {
+            row_starts.push(rows.len());
+            for row in wrap_line(line, inner.width as usize) {
+                rows.push(row);
+                self.row_lines.push(idx);
+            }
+        }
+        let rows_of = |source_row: usize| {
+            let start = row_starts.get(source_row).copied().unwrap_or(0);
+            let end = row_starts
+                .get(source_row + 1)
+                .copied()
+                .unwrap_or(rows.len());
+            (start, end - start)
+        };
+        let active_rows = {
+            let start = self.row_lines.iter().position(|it| *it == current_line);
+            let height = self
+                .row_lines
+                .iter()
+                .filter(|it| **it == current_line)
+                .count();
+            (start.unwrap_or(0), height)
+        };
+
+        let scroll_to = |(row, height): (usize, usize)| {
+            scroll_position(
+                cfg.general.scroll_mode,
+                cfg.general.scroll_offset as usize,
+                row,
+                height,
+                inner.height as usize,
+                rows.len(),
+            )
+        };
+        let scroll_y = if let Some(row) = search_row {
+            scroll_to(rows_of(row))
+        } else if self.search.is_some() {
+            self.last_scroll_y
+        } else if self.autoscroll_enabled && tracker.lyrics.sync_type == LyricSyncType::LineSynced {
+            scroll_to(active_rows)
+        } else {
+            self.scroll_amount
+        };
+        self.last_scroll_y = scroll_y;
+        self.lyrics_area = inner;
+
+        let lyrics_part = Paragraph::new(rows)
             .style(Style::default())
             .alignment(Alignment::Center)
-            .wrap(Wrap { trim: false })
             .scroll((scroll_y, 0))
             .block(lyrics_block);
-        f.render_widget(lyrics_part, horizontal_layout[0]);
+        f.render_widget(lyrics_part, area);
+    }
 
-        // Track info
+    fn render_info(
+        &mut self,
+        tracker: &LyricsTracker,
+        f: &mut TermFrame,
+        area: Rect,
+        cfg: &Arc<VersesConfig>,
+    ) {
         let info_block = Block::new()
             .fg(cfg.theme.borders.info_border_color.0)
             .title("About this track".fg(cfg.theme.borders.info_border_text_color.0))
-            .borders(Borders::ALL)
+            .borders(self.borders())
             .border_type(cfg.theme.borders.info_border_style.0)
             .title_alignment(Alignment::Left);
 
@@ -244,55 +722,53 @@ impl<'a> TerminalUiBackend<'a> {
             self.cached_info_vec.clone()
         } else {
             // rebuild info
-            let mut reg = Handlebars::new();
-            handlebars_helper!(join_helper: |input: Vec<String>| { input.join(", ") });
-            reg.register_helper("join", Box::new(join_helper));
+            let reg = template_registry();
+            // templates are compiled when loading the config, but can still fail to render,
+            // e.g. when a helper is given a value of the wrong type
+            let render = |template: &str, data: serde_json::Value| {
+                let text = reg
+                    .render_template(template, &data)
+                    .unwrap_or_else(|err| format!("Invalid template: {err}"));
+                Line::from(text)
+            };
             let mut info_vec = Vec::with_capacity(4);
             if cfg.general.display.show_name {
-                info_vec.push(Line::from(
-                    reg.render_template(
-                        &cfg.general.display.name_format,
-                        &serde_json::json!({ "name": tracker.track_data.track_name }),
-                    )
-                    .unwrap(),
+                info_vec.push(render(
+                    &cfg.general.display.name_format,
+                    serde_json::json!({ "name": tracker.track_data.track_name }),
                 ));
             };
             if cfg.general.display.show_artists {
-                info_vec.push(Line::from(
-                    reg.render_template(
-                        &cfg.general.display.artists_format,
-                        &serde_json::json!({ "artists": tracker.track_data.track_artists }),
-                    )
-                    .unwrap(),
+                info_vec.push(render(
+                    &cfg.general.display.artists_format,
+                    serde_json::json!({ "artists": tracker.track_data.track_artists }),
                 ));
             };
             if cfg.general.display.show_album {
-                info_vec.push(Line::from(
-                    reg.render_template(
-                        &cfg.general.display.album_format,
-                        &serde_json::json!({ "album": tracker.track_data.track_album }),
-                    )
-                    .unwrap(),
+                info_vec.push(render(
+                    &cfg.general.display.album_format,
+                    serde_json::json!({ "album": tracker.track_data.track_album }),
                 ));
             };
             if cfg.general.display.show_genres {
-                info_vec.push(Line::from(
-                    reg.render_template(
-                        &cfg.general.display.genres_format,
-                        &serde_json::json!({ "genres": tracker.track_data.artist_genres }),
-                    )
-                    .unwrap(),
+                info_vec.push(render(
+                    &cfg.general.display.genres_format,
+                    serde_json::json!({ "genres": tracker.track_data.artist_genres }),
                 ));
             };
             if cfg.general.display.show_popularity {
-                info_vec.push(Line::from(
-                    reg.render_template(
-                        &cfg.general.display.popularity_format,
-                        &serde_json::json!({ "popularity": tracker.track_data.popularity }),
-                    )
-                    .unwrap(),
+                info_vec.push(render(
+                    &cfg.general.display.popularity_format,
+                    serde_json::json!({ "popularity": tracker.track_data.popularity }),
                 ));
             };
+            info_vec.extend(
+                tracker
+                    .track_data
+                    .extra_fields
+                    .iter()
+                    .map(|(key, value)| Line::from(format!("• {key}: {value}"))),
+            );
             let text_color = cfg.theme.borders.info_text_color.0;
             info_vec
                 .iter_mut()
@@ -302,119 +778,336 @@ impl<'a> TerminalUiBackend<'a> {
             info_vec
         };
 
-        let right_side_layout = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([Constraint::Percentage(80), Constraint::Percentage(20)].as_ref())
-            .split(horizontal_layout[1]);
-
         let info_part = Paragraph::new(info_vec)
             .style(Style::default())
             .alignment(Alignment::Left)
             .wrap(Wrap { trim: false })
             .block(info_block);
-        f.render_widget(info_part, right_side_layout[0]);
+        f.render_widget(info_part, area);
+    }
+
+    fn render_karaoke(
+        &self,
+        tracker: &LyricsTracker,
+        f: &mut TermFrame,
+        size: Rect,
+        cfg: &Arc<VersesConfig>,
+    ) {
+        let theme = &cfg.theme.karaoke;
+        let text_color = theme
+            .text_color
+            .as_ref()
+            .unwrap_or(&cfg.theme.lyrics.active_text_color)
+            .0;
+        let next_text_color = theme
+            .next_text_color
+            .as_ref()
+            .unwrap_or(&cfg.theme.lyrics.inactive_text_color)
+            .0;
+
+        let lines = &tracker.lyrics.lines;
+        let current_line = tracker.current_line;
+        // showing the track until the first line is reached
+        let (current, next) = if tracker.lyrics.sync_type == LyricSyncType::LineSynced
+            && current_line >= 0
+            && (current_line as usize) < lines.len()
+        {
+            let current_line = current_line as usize;
+            (
+                lines[current_line].words.clone(),
+                lines
+                    .get(current_line + 1)
+                    .map(|it| it.words.clone())
+                    .unwrap_or_default(),
+            )
+        } else {
+            (
+                tracker.track_data.track_name.clone(),
+                tracker.track_data.track_artists.join(", "),
+            )
+        };
+
+        let width = size.width.saturating_sub(2) as usize;
+        let mut next_rows = wrap_line(&Line::from(next.as_str()), width);
+        if should_romanize(&tracker.lyrics.language, cfg) {
+            if let Some(romanized) = romanize_line(&next) {
+                next_rows.extend(wrap_line(&Line::from(romanized), width));
+            }
+        }
+        next_rows
+            .iter_mut()
+            .for_each(|row| row.patch_style(Style::default().fg(next_text_color)));
+        if let Some(indicator) = self.instrumental_indicator(tracker, cfg) {
+            next_rows.insert(0, indicator);
+        }
+
+        // leaving an empty row between the current and the next line
+        let max_height = (size.height as usize).saturating_sub(next_rows.len() + 1);
+        let current_style = Style::default().fg(text_color).add_modifier(Modifier::BOLD);
+        let mut text = match big_text(&current, width, max_height, theme.scale) {
+            Some(rows) => rows
+                .into_iter()
+                .map(|row| Line::from(Span::styled(row, current_style)))
+                .collect(),
+            // falling back to regular text if the line is too long
+            None => {
+                let mut rows = wrap_line(&Line::from(current.as_str()), width);
+                rows.iter_mut()
+                    .for_each(|row| row.patch_style(current_style));
+                rows
+            }
+        };
+        text.push(Line::default());
+        text.extend(next_rows);
+
+        // centering vertically
+        let padding = (size.height as usize).saturating_sub(text.len()) / 2;
+        let text = std::iter::repeat_with(Line::default)
+            .take(padding)
+            .chain(text)
+            .collect::<Vec<_>>();
+
+        let karaoke_part = Paragraph::new(text)
+            .style(Style::default())
+            .alignment(Alignment::Center);
+        f.render_widget(karaoke_part, size);
+    }
 
-        let controls_block = Block::new()
+    /// Indicator counting down to the next line, if there is an instrumental break
+    fn instrumental_indicator(
+        &self,
+        tracker: &LyricsTracker,
+        cfg: &Arc<VersesConfig>,
+    ) -> Option<Line<'static>> {
+        let brk = InstrumentalBreak::detect(tracker, &cfg.general.instrumental)?;
+        let frame = (self.animation_start.elapsed().as_millis() / 250) as usize;
+        Some(Line::from(Span::styled(
+            brk.indicator(tracker.current_progress_ms, 10, frame),
+            Style::default().fg(cfg.theme.lyrics.active_text_color.0),
+        )))
+    }
+
+    fn render_album_art(
+        &mut self,
+        tracker: &LyricsTracker,
+        f: &mut TermFrame,
+        area: Rect,
+        cfg: &Arc<VersesConfig>,
+    ) {
+        let art_block = Block::new()
             .fg(cfg.theme.borders.info_border_color.0)
-            .title("Controls".fg(cfg.theme.borders.info_border_text_color.0))
+            .borders(self.borders())
+            .border_type(cfg.theme.borders.info_border_style.0);
+        let inner = art_block.inner(area);
+        f.render_widget(art_block, area);
+        let image = match &tracker.track_data.album_art {
+            Some(image) => image,
+            None => return,
+        };
+
+        if self.graphics == GraphicsProtocol::HalfBlocks {
+            let lines = match &self.art_lines {
+                Some((drawn_area, drawn_image, lines))
+                    if *drawn_area == inner && Arc::ptr_eq(drawn_image, image) =>
+                {
+                    lines.clone()
+                }
+                _ => {
+                    let lines = half_blocks(image, inner.width, inner.height);
+                    self.art_lines = Some((inner, image.clone(), lines.clone()));
+                    lines
+                }
+            };
+            f.render_widget(Paragraph::new(lines), inner);
+            return;
+        }
+        // keeping the image square, cells are about twice as tall as wide
+        let columns = inner.width.min(inner.height * 2);
+        let rows = (columns / 2).max(1);
+        let art_area = Rect::new(
+            inner.x + (inner.width - columns) / 2,
+            inner.y,
+            columns,
+            rows,
+        );
+        if art_area.width > 0 {
+            self.pending_art = Some((art_area, image.clone()));
+        }
+    }
+
+    fn render_help(&self, f: &mut TermFrame, size: Rect, cfg: &Arc<VersesConfig>) {
+        let text_color = cfg.theme.borders.info_text_color.0;
+        let autoscroll = self.autoscroll_label();
+        let mut help = vec![Line::from(autoscroll.fg(text_color)), Line::default()];
+        help.extend(cfg.keys.bindings().into_iter().map(|(action, binding)| {
+            let mut spans = Vec::with_capacity(binding.0.len() * 2 + 1);
+            for (idx, chord) in binding.0.iter().enumerate() {
+                if idx != 0 {
+                    spans.push("/".fg(text_color));
+                }
+                spans.push(Span::styled(
+                    chord.to_string(),
+                    Style::default().bg(text_color),
+                ));
+            }
+            spans.push(Span::styled(
+                format!(" - {}", action.description()),
+                Style::default().fg(text_color),
+            ));
+            Line::from(spans)
+        }));
+
+        let width = help.iter().map(Line::width).max().unwrap_or(0) as u16 + 4;
+        let height = help.len() as u16 + 2;
+        let area = centered_rect(width, height, size);
+
+        let help_block = Block::new()
+            .fg(cfg.theme.borders.info_border_color.0)
+            .title("Help".fg(cfg.theme.borders.info_border_text_color.0))
             .borders(Borders::ALL)
             .border_type(cfg.theme.borders.info_border_style.0)
-            .title_alignment(Alignment::Left);
-        let autoscroll = format!(
+            .title_alignment(Alignment::Left)
+            .padding(Padding::horizontal(1));
+        let help_part = Paragraph::new(help)
+            .style(Style::default())
+            .alignment(Alignment::Left)
+            .block(help_block);
+        f.render_widget(Clear, area);
+        f.render_widget(help_part, area);
+    }
+
+    fn render_progress(
+        &mut self,
+        tracker: &LyricsTracker,
+        f: &mut TermFrame,
+        area: Rect,
+        cfg: &Arc<VersesConfig>,
+    ) {
+        let (progress_percent, label) = progress_label(tracker, cfg);
+        let track_progress = Gauge::default()
+            .gauge_style(Style::default().fg(cfg.theme.progress_bar.color.0))
+            .percent(progress_percent)
+            .label(label);
+        self.progress_area = area;
+        f.render_widget(track_progress, area)
+    }
+
+    fn render_status(
+        &self,
+        tracker: &LyricsTracker,
+        f: &mut TermFrame,
+        area: Rect,
+        cfg: &Arc<VersesConfig>,
+    ) {
+        let text_color = cfg.theme.borders.info_text_color.0;
+        let track_name = maybe_romanize_str(
+            &tracker.track_data.track_name,
+            &tracker.lyrics.language,
+            cfg,
+        );
+        let mut status = format!(
+            "{} {} • {} | {}",
+            if tracker.is_playing { "▶" } else { "⏸" },
+            track_name,
+            tracker.track_data.track_artists.join(", "),
+            self.autoscroll_label()
+        );
+        if let Some(profile) = &cfg.profile {
+            status.push_str(&format!(" | Profile: {profile}"));
+        }
+        let status_part = Paragraph::new(Line::from(status.fg(text_color)))
+            .style(Style::default())
+            .alignment(Alignment::Left);
+        f.render_widget(status_part, area);
+    }
+
+    fn autoscroll_label(&self) -> String {
+        format!(
             "Autoscroll: {}",
             if self.autoscroll_enabled {
                 "enabled"
             } else {
                 "disabled"
             }
-        );
-        let controls_part = Paragraph::new(vec![
-            Line::from(autoscroll.fg(cfg.theme.borders.info_text_color.0)),
-            Line::from(vec![
-                "q".bg(cfg.theme.borders.info_text_color.0),
-                "   - Quit".fg(cfg.theme.borders.info_text_color.0),
-            ]),
-            Line::from(vec![
-                "j".bg(cfg.theme.borders.info_text_color.0),
-                "/".fg(cfg.theme.borders.info_text_color.0),
-                "k".bg(cfg.theme.borders.info_text_color.0),
-                " - Scroll (down/up)".fg(cfg.theme.borders.info_text_color.0),
-            ]),
-            Line::from(vec![
-                "a".bg(cfg.theme.borders.info_text_color.0),
-                "   - Toggle autoscroll".fg(cfg.theme.borders.info_text_color.0),
-            ]),
-            Line::from(vec![
-                "r".bg(cfg.theme.borders.info_text_color.0),
-                "   - Reset scroll".fg(cfg.theme.borders.info_text_color.0),
-            ]),
-        ])
-        .style(Style::default())
-        .alignment(Alignment::Left)
-        .wrap(Wrap { trim: false })
-        .block(controls_block);
-        f.render_widget(controls_part, right_side_layout[1]);
-
-        // Track progress
-        let progress_percent = f32::ceil(
-            (tracker.current_progress_ms as f32
-                / tracker.track_data.track_duration.as_millis() as f32)
-                * 100f32,
-        ) as u16;
-        let label = if cfg.theme.progress_bar.is_percentage {
-            format!("{progress_percent}%")
-        } else {
-            format!(
-                "{} / {}",
-                fmt_duration(tracker.current_progress_ms),
-                fmt_duration(tracker.track_data.track_duration.as_millis() as u32)
-            )
-        };
-        let track_progress = Gauge::default()
-            .gauge_style(Style::default().fg(cfg.theme.progress_bar.color.0))
-            .percent(progress_percent)
-            .label(label);
-        f.render_widget(track_progress, vertical_layout[1])
+        )
     }
 }
 
-#[inline]
-fn fmt_duration(duration_ms: u32) -> String {
-    let mut seconds = duration_ms / 1000;
-    let minutes = seconds / 60;
-    seconds %= 60;
-    format!("{minutes:0>2}:{seconds:0>2}")
+/// Returns the scroll position, which keeps the rows of the active line
+/// where configured by the scroll mode
+fn scroll_position(
+    mode: ScrollMode,
+    offset: usize,
+    row: usize,
+    height: usize,
+    view_height: usize,
+    total_rows: usize,
+) -> u16 {
+    let scroll = match mode {
+        // keeping the row visible even if the offset does not fit
+        ScrollMode::TopOffset => row.saturating_sub(offset.min(view_height.saturating_sub(1))),
+        // lines taller than the view are aligned to the top instead
+        ScrollMode::Center if height >= view_height => row,
+        ScrollMode::Center => (row + height / 2).saturating_sub(view_height / 2),
+        ScrollMode::Page => row.checked_div(view_height).unwrap_or(0) * view_height,
+    };
+    scroll.min(total_rows.saturating_sub(view_height)) as u16
+}
+
+/// Returns a rect of the provided size in the middle of the area,
+/// shrunk to fit if the area is too small
+fn centered_rect(width: u16, height: u16, area: Rect) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+fn contains(area: Rect, column: u16, row: u16) -> bool {
+    column >= area.x && column < area.right() && row >= area.y && row < area.bottom()
+}
+
+/// Returns track progress in percents, together with its textual representation
+fn progress_label(tracker: &LyricsTracker, cfg: &Arc<VersesConfig>) -> (u16, String) {
+    let progress_percent = f32::ceil(
+        (tracker.current_progress_ms as f32 / tracker.track_data.track_duration.as_millis() as f32)
+            * 100f32,
+    ) as u16;
+    let progress_percent = progress_percent.min(100);
+    let label = if cfg.theme.progress_bar.is_percentage {
+        format!("{progress_percent}%")
+    } else {
+        format!(
+            "{} / {}",
+            fmt_duration(tracker.current_progress_ms),
+            fmt_duration(tracker.track_data.track_duration.as_millis() as u32)
+        )
+    };
+    (progress_percent, label)
 }
 
 #[inline]
-fn setup_terminal() -> anyhow::Result<Term> {
+fn setup_terminal(mouse: bool) -> anyhow::Result<Term> {
     let mut stdout = std::io::stdout();
     enable_raw_mode()?;
     crossterm::execute!(stdout, EnterAlternateScreen,)?;
+    if mouse {
+        crossterm::execute!(stdout, EnableMouseCapture)?;
+    }
     Ok(Terminal::new(CrosstermBackend::new(stdout))?)
 }
 
 #[inline]
 fn restore_terminal(terminal: &mut Term) -> anyhow::Result<()> {
     disable_raw_mode()?;
-    crossterm::execute!(terminal.backend_mut(), LeaveAlternateScreen,)?;
+    crossterm::execute!(
+        terminal.backend_mut(),
+        DisableMouseCapture,
+        LeaveAlternateScreen,
+    )?;
     terminal.show_cursor().map_err(anyhow::Error::from)
 }
-
-#[inline]
-fn maybe_romanize_str(name: &str, language: &str, cfg: &Arc<VersesConfig>) -> String {
-    if cfg.general.romanize_unicode
-        && cfg.general.romanize_track_names
-        && language != "en"
-        && !cfg.general.romanize_exclude.contains(&language.to_owned())
-    {
-        let romanized = deunicode(name);
-        if romanized == name {
-            romanized
-        } else {
-            format!("{name} ({romanized})")
-        }
-    } else {
-        name.to_owned()
-    }
-}
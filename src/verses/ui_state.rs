@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{config::layout::PaneKind, paths};
+
+/// Visibility of TUI elements, toggled at runtime and persisted between launches
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiState {
+    pub show_info: bool,
+    pub show_progress: bool,
+    pub show_borders: bool,
+}
+
+impl Default for UiState {
+    fn default() -> Self {
+        Self {
+            show_info: true,
+            show_progress: true,
+            show_borders: true,
+        }
+    }
+}
+
+impl UiState {
+    /// Loads the persisted state, falling back to defaults if it is missing or broken
+    pub async fn load() -> Self {
+        let paths = match paths::get() {
+            Ok(paths) => paths,
+            Err(_) => return Self::default(),
+        };
+        // older versions kept the state in the cache directory
+        for path in [paths.ui_state(), paths.cache_dir.join("ui_state.json")] {
+            if let Ok(data) = tokio::fs::read(path).await {
+                return serde_json::from_slice(&data).unwrap_or_default();
+            }
+        }
+        Self::default()
+    }
+
+    pub async fn save(&self) -> anyhow::Result<()> {
+        let path = paths::get()?.ui_state();
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(path, serde_json::to_vec(self)?).await?;
+        Ok(())
+    }
+
+    /// Panes that were hidden by the user
+    pub fn hidden_panes(&self) -> Vec<PaneKind> {
+        let mut hidden = Vec::with_capacity(2);
+        if !self.show_info {
+            hidden.push(PaneKind::Info);
+        }
+        if !self.show_progress {
+            hidden.push(PaneKind::Progress);
+        }
+        hidden
+    }
+}